@@ -291,8 +291,8 @@ export class PoolService {
       return {
         poolPda: poolPda.toBase58(),
         admin: poolData.admin.toBase58(),
-        usdcVault: poolData.usdcVault.toBase58(),
-        guaranteeReserveUsdc: poolData.guaranteeReserveUsdc.toString(),
+        vault: poolData.vault.toBase58(),
+        guaranteeReserveAmount: poolData.guaranteeReserveAmount.toString(),
         bump: poolData.bump,
       };
     } catch (error) {
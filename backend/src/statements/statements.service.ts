@@ -81,10 +81,9 @@ export class StatementsService {
         this.programs.creditLineProgramId,
       );
 
-      const cycleIdBuf = Buffer.from(cycleId.padEnd(8, '\0').slice(0, 8));
-
+      // O PDA da fatura on-chain é derivado do contador de ciclos da conta
       await (this.programs.creditLine as any).methods
-        .statementClose(Array.from(cycleIdBuf) as any)
+        .statementClose()
         .accounts({
           creditAccount: creditPda,
         })
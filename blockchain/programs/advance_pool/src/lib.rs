@@ -1,40 +1,147 @@
 use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self as spl_token, Mint, TokenAccount, TokenInterface};
+use receivables::program::Receivables;
+use receivables::NoteState;
 
 declare_id!("8zKbc5hProPy7xB2M5iDKABCLhcb68ezdyAixiC7NcDe");
 
+/// Programa credit_line: só o PDA `credit_vault` dele saca do pool (saque em dinheiro).
+pub const CREDIT_LINE_PROGRAM_ID: Pubkey = pubkey!("2DgViSNpi9CLMHjLmzqJGHvTGMjZXo97pMufmXJuqAQs");
+
 #[program]
 pub mod advance_pool {
     use super::*;
 
     pub fn init_pool(ctx: Context<InitPool>) -> Result<()> {
         let p = &mut ctx.accounts.pool;
-        p.usdc_vault = Pubkey::default();
-        p.guarantee_reserve_usdc = 0;
+        p.vault = Pubkey::default();
+        p.guarantee_reserve_amount = 0;
+        p.disbursed_amount = 0;
         p.admin = ctx.accounts.admin.key();
         p.bump = ctx.bumps.pool;
         Ok(())
     }
 
+    /// Antecipação: o pool compra a nota do lojista pagando o líquido (desconto = bruto da
+    /// nota - líquido calculado pelo credit_line conforme o plano) e vira o beneficiário.
+    /// Admin do pool e lojista assinam; a cessão é feita via CPI no `receivables`, que marca
+    /// a nota como `advanced`.
     pub fn advance(ctx: Context<Advance>) -> Result<()> {
-        // Em MVP, só troca beneficiário via semântica off-chain
-        emit!(Advanced { note: ctx.accounts.note_state.key(), gross: ctx.accounts.note_state.amount_usdc, discount: 0, net: ctx.accounts.note_state.amount_usdc });
-        // Atualiza beneficiário para o pool (requere admin para bypass da assinatura do merchant)
-        ctx.accounts.note_state.beneficiary = ctx.accounts.pool.key();
+        let note = &ctx.accounts.note_state;
+        require!(!note.disputed, PoolError::NoteDisputed);
+        require!(
+            note.status == receivables::NOTE_STATUS_ISSUED && note.beneficiary == note.merchant,
+            PoolError::NoteNotAdvanceable
+        );
+        // O pool só antecipa notas na moeda do seu cofre
+        require_keys_eq!(note.mint, ctx.accounts.vault.mint, PoolError::MintMismatch);
+        let gross = note.amount;
+        let net = note.net_amount.min(gross);
+        let note_key = note.key();
+
+        let pool = &ctx.accounts.pool;
+        let seeds: &[&[u8]] = &[b"pool", pool.admin.as_ref(), &[pool.bump]];
+        let signer = &[seeds];
+        let cpi_accounts = spl_token::TransferChecked {
+            from: ctx.accounts.vault.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.merchant_ata.to_account_info(),
+            authority: ctx.accounts.pool.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            signer,
+        );
+        spl_token::transfer_checked(cpi_ctx, net, ctx.accounts.mint.decimals)?;
+
+        let cpi_accounts = receivables::cpi::accounts::AdvanceNote {
+            beneficiary: ctx.accounts.merchant.to_account_info(),
+            pool_admin: ctx.accounts.admin.to_account_info(),
+            pool: ctx.accounts.pool.to_account_info(),
+            note_state: ctx.accounts.note_state.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.receivables_program.to_account_info(),
+            cpi_accounts,
+            signer,
+        );
+        receivables::cpi::advance_note(cpi_ctx)?;
+
+        emit!(Advanced { note: note_key, gross, discount: gross - net, net });
         Ok(())
     }
 
     pub fn guarantee_settle(ctx: Context<GuaranteeSettle>) -> Result<()> {
         // Em MVP, só evento; produção: debit da reserva e pagamento ao merchant
-        emit!(GuaranteeSettled { note: ctx.accounts.note_state.key(), amount: ctx.accounts.note_state.amount_usdc });
-        // Beneficiário já deve ser o pool; se não, assumir
-        ctx.accounts.note_state.beneficiary = ctx.accounts.pool.key();
+        emit!(GuaranteeSettled {
+            note: ctx.accounts.note_state.key(),
+            amount: ctx.accounts.note_state.amount
+        });
+        Ok(())
+    }
+
+    /// Define o cofre do pool (autoridade = PDA do pool), na moeda das notas que ele antecipa.
+    pub fn set_vault(ctx: Context<SetVault>) -> Result<()> {
+        ctx.accounts.pool.vault = ctx.accounts.vault.key();
+        Ok(())
+    }
+
+    /// Saque em dinheiro do credit_line: transfere do cofre do pool ao destinatário.
+    /// Só o PDA `credit_vault` do credit_line assina.
+    pub fn disburse(ctx: Context<Disburse>, amount: u64) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let seeds: &[&[u8]] = &[b"pool", pool.admin.as_ref(), &[pool.bump]];
+        let signer = &[seeds];
+        let cpi_accounts = spl_token::TransferChecked {
+            from: ctx.accounts.vault.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.recipient.to_account_info(),
+            authority: ctx.accounts.pool.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            signer,
+        );
+        spl_token::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)?;
+
+        let p = &mut ctx.accounts.pool;
+        p.disbursed_amount = p.disbursed_amount.saturating_add(amount);
+        emit!(Disbursed { recipient: ctx.accounts.recipient.key(), amount });
+        Ok(())
+    }
+
+    /// Devolução do principal de saques pago ao credit_line; baixa `disbursed_amount`.
+    pub fn repay_disbursed(ctx: Context<RepayDisbursed>, amount: u64) -> Result<()> {
+        let cpi_accounts = spl_token::TransferChecked {
+            from: ctx.accounts.source.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.vault.to_account_info(),
+            authority: ctx.accounts.credit_authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        spl_token::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)?;
+
+        let p = &mut ctx.accounts.pool;
+        p.disbursed_amount = p.disbursed_amount.saturating_sub(amount);
+        emit!(DisbursedRepaid { amount, disbursed_amount: p.disbursed_amount });
+        Ok(())
+    }
+
+    /// Baixa como perda do principal de saques de uma conta encerrada pelo credit_line
+    /// (`force_close_account`); reduz `disbursed_amount`. Só o PDA `credit_vault` assina.
+    pub fn write_off_disbursed(ctx: Context<WriteOffDisbursed>, amount: u64) -> Result<()> {
+        let p = &mut ctx.accounts.pool;
+        p.disbursed_amount = p.disbursed_amount.saturating_sub(amount);
+        emit!(DisbursedWrittenOff { amount, disbursed_amount: p.disbursed_amount });
         Ok(())
     }
 
     pub fn replenish_reserve(ctx: Context<ReplenishReserve>, amount: u64) -> Result<()> {
         let p = &mut ctx.accounts.pool;
         require!(ctx.accounts.admin.key() == p.admin, PoolError::Unauthorized);
-        p.guarantee_reserve_usdc = p.guarantee_reserve_usdc.saturating_add(amount);
+        p.guarantee_reserve_amount = p.guarantee_reserve_amount.saturating_add(amount);
         emit!(ReserveReplenished { amount });
         Ok(())
     }
@@ -42,8 +149,9 @@ pub mod advance_pool {
 
 #[account]
 pub struct Pool {
-    pub usdc_vault: Pubkey,
-    pub guarantee_reserve_usdc: u64,
+    pub vault: Pubkey,
+    pub guarantee_reserve_amount: u64,
+    pub disbursed_amount: u64, // total sacado pelo credit_line (saque em dinheiro)
     pub admin: Pubkey,
     pub bump: u8,
 }
@@ -65,22 +173,76 @@ pub struct InitPool<'info> {
 
 #[derive(Accounts)]
 pub struct Advance<'info> {
-    #[account(mut)]
+    #[account(seeds = [b"pool", admin.key().as_ref()], bump = pool.bump, has_one = admin)]
     pub pool: Account<'info, Pool>,
-    /// CHECK: admin must sign to force beneficiary change
     pub admin: Signer<'info>,
+    /// Lojista emissor da nota, que a vende ao pool
+    #[account(address = note_state.merchant @ PoolError::Unauthorized)]
+    pub merchant: Signer<'info>,
+    #[account(mut, address = pool.vault, token::mint = mint)]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, token::mint = mint, token::authority = merchant)]
+    pub merchant_ata: InterfaceAccount<'info, TokenAccount>,
     #[account(mut)]
-    pub note_state: Account<'info, crate::receivables_stub::NoteState>,
+    pub note_state: Account<'info, NoteState>,
+    pub receivables_program: Program<'info, Receivables>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
 pub struct GuaranteeSettle<'info> {
-    #[account(mut)]
+    #[account(seeds = [b"pool", admin.key().as_ref()], bump = pool.bump, has_one = admin)]
     pub pool: Account<'info, Pool>,
-    /// CHECK: admin must sign to force beneficiary change
     pub admin: Signer<'info>,
-    #[account(mut)]
-    pub note_state: Account<'info, crate::receivables_stub::NoteState>,
+    /// Só notas já antecipadas ao pool
+    #[account(constraint = note_state.beneficiary == pool.key() @ PoolError::Unauthorized)]
+    pub note_state: Account<'info, NoteState>,
+}
+
+#[derive(Accounts)]
+pub struct SetVault<'info> {
+    pub admin: Signer<'info>,
+    #[account(mut, has_one = admin)]
+    pub pool: Account<'info, Pool>,
+    #[account(token::authority = pool)]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct Disburse<'info> {
+    #[account(seeds = [b"credit_vault"], bump, seeds::program = CREDIT_LINE_PROGRAM_ID)]
+    pub credit_authority: Signer<'info>,
+    #[account(mut, seeds = [b"pool", pool.admin.as_ref()], bump = pool.bump)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, address = pool.vault, token::mint = mint)]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, token::mint = mint)]
+    pub recipient: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct RepayDisbursed<'info> {
+    #[account(seeds = [b"credit_vault"], bump, seeds::program = CREDIT_LINE_PROGRAM_ID)]
+    pub credit_authority: Signer<'info>,
+    #[account(mut, seeds = [b"pool", pool.admin.as_ref()], bump = pool.bump)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, address = pool.vault, token::mint = mint)]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, token::mint = mint, token::authority = credit_authority)]
+    pub source: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct WriteOffDisbursed<'info> {
+    #[account(seeds = [b"credit_vault"], bump, seeds::program = CREDIT_LINE_PROGRAM_ID)]
+    pub credit_authority: Signer<'info>,
+    #[account(mut, seeds = [b"pool", pool.admin.as_ref()], bump = pool.bump)]
+    pub pool: Account<'info, Pool>,
 }
 
 #[derive(Accounts)]
@@ -105,6 +267,24 @@ pub struct GuaranteeSettled {
     pub amount: u64,
 }
 
+#[event]
+pub struct Disbursed {
+    pub recipient: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct DisbursedRepaid {
+    pub amount: u64,
+    pub disbursed_amount: u64,
+}
+
+#[event]
+pub struct DisbursedWrittenOff {
+    pub amount: u64,
+    pub disbursed_amount: u64,
+}
+
 #[event]
 pub struct ReserveReplenished {
     pub amount: u64,
@@ -116,21 +296,10 @@ pub enum PoolError {
     InsufficientReserve,
     #[msg("Unauthorized")]
     Unauthorized,
-}
-
-// ------- Minimal stub to reuse NoteState layout without CPI cross-crate -------
-pub mod receivables_stub {
-    use super::*;
-    #[account]
-    pub struct NoteState {
-        pub note_id: Pubkey,
-        pub merchant: Pubkey,
-        pub beneficiary: Pubkey,
-        pub buyer: Pubkey,
-        pub amount_usdc: u64,
-        pub due_ts: i64,
-        pub status: u8,
-        pub order_id: [u8; 32],
-        pub bump: u8,
-    }
+    #[msg("Note is under dispute")]
+    NoteDisputed,
+    #[msg("Note mint does not match the pool vault")]
+    MintMismatch,
+    #[msg("Note is not available for advance")]
+    NoteNotAdvanceable,
 }
@@ -16,10 +16,16 @@ declare_id!("6UB2YQb1VN5fT99vVKX8LQ2YGnSQAALguPdikQtEpjaY");
 pub struct VaultConfig {
     pub admin: Pubkey,
     pub bump: u8,
+    pub liquidation_authority: Pubkey, // PDA do credit_line autorizado a executar colateral
+    pub oracle: Pubkey, // além do admin, pode publicar preços (default = só o admin)
     // adicione outros parâmetros globais se quiser
 }
 impl VaultConfig {
-    pub const SIZE: usize = 32 + 1;
+    pub const SIZE: usize = 32 + 1 + 32 + 32;
+
+    pub fn is_price_authority(&self, key: &Pubkey) -> bool {
+        *key == self.admin || (self.oracle != Pubkey::default() && *key == self.oracle)
+    }
 }
 
 #[account]
@@ -45,6 +51,17 @@ impl Position {
     pub const SIZE: usize = 32 + 32 + 8 + 4 + 8 + 1;
 }
 
+/// Quantas posições o owner já abriu; permite a quem avalia o colateral exigir o conjunto completo.
+#[account]
+pub struct PositionIndex {
+    pub owner: Pubkey,
+    pub count: u8,
+    pub bump: u8,
+}
+impl PositionIndex {
+    pub const SIZE: usize = 32 + 1 + 1;
+}
+
 /* --- Estruturas simples para a parte "pump" (parametrização local) --- */
 
 #[account]
@@ -158,6 +175,13 @@ pub struct SetPumpTokenParams<'info> {
     )]
     pub pump_class: Account<'info, PumpClass>,
 
+    #[account(
+        seeds = [b"vault_cfg"],
+        bump = vault_config.bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub vault_config: Account<'info, VaultConfig>,
+
     #[account(mut)]
     pub admin: Signer<'info>,
 
@@ -168,7 +192,7 @@ pub struct SetPumpTokenParams<'info> {
 pub struct SetPrice<'info> {
     #[account(
         init_if_needed,
-        payer = admin,
+        payer = authority,
         space = 8 + PriceAccount::SIZE,
         seeds = [b"price", mint.key().as_ref()],
         bump
@@ -177,8 +201,16 @@ pub struct SetPrice<'info> {
 
     pub mint: InterfaceAccount<'info, Mint>,
 
+    #[account(
+        seeds = [b"vault_cfg"],
+        bump = vault_config.bump,
+        constraint = vault_config.is_price_authority(&authority.key()) @ ErrorCode::Unauthorized
+    )]
+    pub vault_config: Account<'info, VaultConfig>,
+
+    /// Admin ou oráculo
     #[account(mut)]
-    pub admin: Signer<'info>,
+    pub authority: Signer<'info>,
 
     pub system_program: Program<'info, System>,
 }
@@ -194,6 +226,15 @@ pub struct OpenPositionPump<'info> {
     )]
     pub position: Account<'info, Position>,
 
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + PositionIndex::SIZE,
+        seeds = [b"pos_index", owner.key().as_ref()],
+        bump
+    )]
+    pub position_index: Account<'info, PositionIndex>,
+
     #[account(mut)]
     pub owner: Signer<'info>,
 
@@ -202,6 +243,25 @@ pub struct OpenPositionPump<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct RevaluePosition<'info> {
+    #[account(
+        mut,
+        seeds = [b"pos", position.owner.as_ref(), mint.key().as_ref()],
+        bump = position.bump,
+        has_one = mint
+    )]
+    pub position: Account<'info, Position>,
+
+    #[account(
+        seeds = [b"price", mint.key().as_ref()],
+        bump = price_account.bump
+    )]
+    pub price_account: Account<'info, PriceAccount>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+}
+
 /* --- (Opcional) Depósito e Saque em ATA do PDA do Vault --- */
 
 #[derive(Accounts)]
@@ -221,6 +281,15 @@ pub struct DepositPump<'info> {
 
     pub mint: InterfaceAccount<'info, Mint>,
 
+    #[account(
+        mut,
+        seeds = [b"pos", owner.key().as_ref(), mint.key().as_ref()],
+        bump = position.bump,
+        has_one = owner,
+        has_one = mint
+    )]
+    pub position: Account<'info, Position>,
+
     /// ATA do dono (fonte)
     #[account(
         mut,
@@ -251,10 +320,14 @@ pub struct DepositPump<'info> {
 pub struct WithdrawPump<'info> {
     #[account(
         seeds = [b"vault_cfg"],
-        bump = vault_config.bump
+        bump = vault_config.bump,
+        has_one = liquidation_authority @ ErrorCode::Unauthorized
     )]
     pub vault_config: Account<'info, VaultConfig>,
 
+    /// PDA do credit_line: o saque passa por ele, que confere o HF da linha de crédito do owner
+    pub liquidation_authority: Signer<'info>,
+
     #[account(
         mut,
         seeds = [b"vault", mint.key().as_ref()],
@@ -264,6 +337,15 @@ pub struct WithdrawPump<'info> {
 
     pub mint: InterfaceAccount<'info, Mint>,
 
+    #[account(
+        mut,
+        seeds = [b"pos", owner.key().as_ref(), mint.key().as_ref()],
+        bump = position.bump,
+        has_one = owner,
+        has_one = mint
+    )]
+    pub position: Account<'info, Position>,
+
     /// ATA do PDA do vault (fonte)
     #[account(
         mut,
@@ -290,6 +372,96 @@ pub struct WithdrawPump<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct SetPositionLtv<'info> {
+    #[account(
+        seeds = [b"vault_cfg"],
+        bump = vault_config.bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub vault_config: Account<'info, VaultConfig>,
+
+    #[account(mut)]
+    pub position: Account<'info, Position>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetLiquidationAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault_cfg"],
+        bump = vault_config.bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub vault_config: Account<'info, VaultConfig>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetOracle<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault_cfg"],
+        bump = vault_config.bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub vault_config: Account<'info, VaultConfig>,
+
+    pub admin: Signer<'info>,
+}
+
+/* --- Execução de colateral em liquidação (chamada via CPI pelo credit_line) --- */
+
+#[derive(Accounts)]
+pub struct SeizeCollateral<'info> {
+    #[account(
+        seeds = [b"vault_cfg"],
+        bump = vault_config.bump,
+        has_one = liquidation_authority @ ErrorCode::Unauthorized
+    )]
+    pub vault_config: Account<'info, VaultConfig>,
+
+    pub liquidation_authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault", mint.key().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"pos", position.owner.as_ref(), mint.key().as_ref()],
+        bump = position.bump,
+        has_one = mint
+    )]
+    pub position: Account<'info, Position>,
+
+    /// ATA do PDA do vault (fonte)
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault,
+        associated_token::token_program = token_program
+    )]
+    pub vault_ata: InterfaceAccount<'info, TokenAccount>,
+
+    /// Conta de token do liquidante (destino)
+    #[account(
+        mut,
+        token::mint = mint,
+        token::token_program = token_program
+    )]
+    pub recipient_ata: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, Token>,
+}
+
 /* =======================================================================================
    PROGRAM
    ======================================================================================= */
@@ -303,6 +475,22 @@ pub mod collateral_vault {
         let cfg = &mut ctx.accounts.vault_config;
         cfg.admin = ctx.accounts.admin.key();
         cfg.bump = ctx.bumps.vault_config; // sem get()
+        cfg.liquidation_authority = Pubkey::default();
+        cfg.oracle = Pubkey::default();
+        Ok(())
+    }
+
+    pub fn set_liquidation_authority(
+        ctx: Context<SetLiquidationAuthority>,
+        liquidation_authority: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.vault_config.liquidation_authority = liquidation_authority;
+        Ok(())
+    }
+
+    /// Chave do oráculo que publica preços (Pubkey::default() = só o admin).
+    pub fn set_oracle(ctx: Context<SetOracle>, oracle: Pubkey) -> Result<()> {
+        ctx.accounts.vault_config.oracle = oracle;
         Ok(())
     }
 
@@ -333,11 +521,12 @@ pub mod collateral_vault {
         Ok(())
     }
 
-    pub fn set_price(ctx: Context<SetPrice>, price_usdc_6: u64, ts: i64) -> Result<()> {
+    /// Só admin ou oráculo; o preço é carimbado com o relógio da chain.
+    pub fn set_price(ctx: Context<SetPrice>, price_usdc_6: u64) -> Result<()> {
         let pa = &mut ctx.accounts.price_account;
         pa.mint = ctx.accounts.mint.key();
         pa.price_usdc_6 = price_usdc_6;
-        pa.last_ts = ts;
+        pa.last_ts = Clock::get()?.unix_timestamp;
         pa.bump = ctx.bumps.price_account;
         Ok(())
     }
@@ -350,6 +539,27 @@ pub mod collateral_vault {
         pos.ltv_bps = 0;
         pos.valuation_usdc = 0;
         pos.bump = ctx.bumps.position;
+
+        let index = &mut ctx.accounts.position_index;
+        index.owner = ctx.accounts.owner.key();
+        index.count = index.count.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        index.bump = ctx.bumps.position_index;
+        Ok(())
+    }
+
+    pub fn set_position_ltv(ctx: Context<SetPositionLtv>, ltv_bps: u32) -> Result<()> {
+        require!(ltv_bps <= 10_000, ErrorCode::InvalidLtv);
+        ctx.accounts.position.ltv_bps = ltv_bps;
+        Ok(())
+    }
+
+    /// Permissionless: atualiza `valuation_usdc` (USDC 6 casas) com o último preço do mint.
+    /// Só informativo: o credit_line avalia quantidade × preço atual a cada uso.
+    pub fn revalue_position(ctx: Context<RevaluePosition>) -> Result<()> {
+        let unit = 10u128.pow(ctx.accounts.mint.decimals as u32);
+        let pos = &mut ctx.accounts.position;
+        let value = pos.amount as u128 * ctx.accounts.price_account.price_usdc_6 as u128 / unit;
+        pos.valuation_usdc = value.min(u64::MAX as u128) as u64;
         Ok(())
     }
 
@@ -370,12 +580,16 @@ pub mod collateral_vault {
         // decimals do mint: usa leitura do account (via interface)
         let decimals = ctx.accounts.mint.decimals;
         spl_token::transfer_checked(cpi_ctx, amount, decimals)?;
+
+        let pos = &mut ctx.accounts.position;
+        pos.amount = pos.amount.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
         Ok(())
     }
 
     pub fn withdraw_pump(ctx: Context<WithdrawPump>, amount: u64) -> Result<()> {
+        require!(amount <= ctx.accounts.position.amount, ErrorCode::InsufficientCollateral);
+
         // vault (PDA) transfere do seu ATA para o ATA do owner — precisa de signer seeds
-        let vault_key = ctx.accounts.vault.key();
         let bump = ctx.accounts.vault.bump;
         let mint_key = ctx.accounts.mint.key();
 
@@ -399,6 +613,33 @@ pub mod collateral_vault {
         );
         let decimals = ctx.accounts.mint.decimals;
         spl_token::transfer_checked(cpi_ctx, amount, decimals)?;
+
+        ctx.accounts.position.amount -= amount;
+        Ok(())
+    }
+
+    pub fn seize_collateral(ctx: Context<SeizeCollateral>, amount: u64) -> Result<()> {
+        require!(amount <= ctx.accounts.position.amount, ErrorCode::InsufficientCollateral);
+
+        let bump = ctx.accounts.vault.bump;
+        let mint_key = ctx.accounts.mint.key();
+        let seeds: &[&[u8]] = &[b"vault", mint_key.as_ref(), &[bump]];
+        let signer = &[seeds];
+
+        let cpi_accounts = spl_token::TransferChecked {
+            from: ctx.accounts.vault_ata.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.recipient_ata.to_account_info(),
+            authority: ctx.accounts.vault.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            signer,
+        );
+        spl_token::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)?;
+
+        ctx.accounts.position.amount -= amount;
         Ok(())
     }
 }
@@ -411,4 +652,10 @@ pub mod collateral_vault {
 pub enum ErrorCode {
     #[msg("Unauthorized")]
     Unauthorized,
+    #[msg("Insufficient collateral in position")]
+    InsufficientCollateral,
+    #[msg("LTV must be at most 10000 bps")]
+    InvalidLtv,
+    #[msg("Math overflow")]
+    MathOverflow,
 }
@@ -1,232 +1,4091 @@
+use advance_pool::{program::AdvancePool, Pool};
 use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{self as spl_token, Mint, TokenAccount, TokenInterface as Token},
+};
+use collateral_vault::{
+    program::CollateralVault, Position, PositionIndex, PriceAccount, Vault, VaultConfig,
+};
+use receivables::program::Receivables;
 
 declare_id!("2DgViSNpi9CLMHjLmzqJGHvTGMjZXo97pMufmXJuqAQs");
 
+pub const MAX_INSTALLMENTS: usize = 12;
+pub const MAX_DELEGATE_MERCHANTS: usize = 8;
+pub const MAX_SETTLEMENT_MINTS: usize = 4;
+/// Faturas vencidas guardadas em separado; as mais antigas se acumulam no último saldo.
+pub const PAST_DUE_CYCLES: usize = 6;
+pub const SECONDS_PER_DAY: i64 = 86_400;
+pub const HOURS_PER_DAY: usize = 24;
+pub const DAYS_PER_WEEK: usize = 7;
+/// Antecedência, em relação ao vencimento, a partir da qual o débito automático pode rodar.
+pub const AUTOPAY_WINDOW_SECS: i64 = 3 * SECONDS_PER_DAY;
+/// Score mínimo de cada faixa (0..=1000); índice = faixa.
+pub const SCORE_BAND_THRESHOLDS: [u16; 4] = [0, 400, 600, 800];
+/// Escala de ponto fixo das taxas na tabela Price (1e9 = 100%).
+pub const RATE_SCALE: u128 = 1_000_000_000;
+
 #[program]
 pub mod credit_line {
     use super::*;
 
     pub fn init_config(ctx: Context<InitConfig>, params: ConfigParams) -> Result<()> {
+        params.validate()?;
         let cfg = &mut ctx.accounts.config;
-        cfg.min_hf_bps_for_new_charges = params.min_hf_bps_for_new_charges;
-        cfg.min_hf_bps_for_withdraw = params.min_hf_bps_for_withdraw;
-        cfg.penalty_rate_bps_daily = params.penalty_rate_bps_daily;
-        cfg.late_fee_bps = params.late_fee_bps;
-        cfg.grace_volatile_days = params.grace_volatile_days;
-        cfg.grace_any_days = params.grace_any_days;
+        cfg.apply(&params);
         cfg.admin = ctx.accounts.admin.key();
+        cfg.pending_admin = Pubkey::default();
+        cfg.pending_eta = 0;
+        cfg.usdc_mint = ctx.accounts.usdc_mint.key();
+        cfg.vault_authority_bump = ctx.bumps.vault_authority;
         cfg.bump = ctx.bumps.config;
+        // Primeira moeda de liquidação; outras via `set_settlement_mint`
+        cfg.settlement_mints[0] =
+            SettlementMint::usdc(cfg.usdc_mint, ctx.accounts.usdc_mint.decimals);
+        cfg.settlement_mint_count = 1;
+        Ok(())
+    }
+
+    /// Agenda novos parâmetros de risco; só valem após `config_delay_secs` (timelock).
+    pub fn propose_config_update(ctx: Context<AdminConfig>, params: ConfigParams) -> Result<()> {
+        params.validate()?;
+        let cfg = &mut ctx.accounts.config;
+        let eta = Clock::get()?
+            .unix_timestamp
+            .saturating_add(cfg.config_delay_secs);
+        emit!(ConfigUpdateProposed {
+            params: params.clone(),
+            eta
+        });
+        cfg.pending_params = params;
+        cfg.pending_eta = eta;
+        Ok(())
+    }
+
+    pub fn cancel_config_update(ctx: Context<AdminConfig>) -> Result<()> {
+        let cfg = &mut ctx.accounts.config;
+        require!(cfg.pending_eta != 0, CreditError::NoPendingUpdate);
+        cfg.pending_eta = 0;
+        cfg.pending_params = ConfigParams::default();
+        emit!(ConfigUpdateCancelled {});
+        Ok(())
+    }
+
+    /// Permissionless: aplica a proposta pendente depois do timelock.
+    pub fn apply_config_update(ctx: Context<ApplyConfigUpdate>) -> Result<()> {
+        let cfg = &mut ctx.accounts.config;
+        require!(cfg.pending_eta != 0, CreditError::NoPendingUpdate);
+        require!(
+            Clock::get()?.unix_timestamp >= cfg.pending_eta,
+            CreditError::TimelockNotElapsed
+        );
+        let params = std::mem::take(&mut cfg.pending_params);
+        cfg.apply(&params);
+        cfg.pending_eta = 0;
+        emit!(ConfigUpdated { params });
+        Ok(())
+    }
+
+    /// Passo 1 da troca de admin; o novo admin precisa aceitar (`accept_admin`).
+    pub fn propose_admin(ctx: Context<AdminConfig>, new_admin: Pubkey) -> Result<()> {
+        let cfg = &mut ctx.accounts.config;
+        cfg.pending_admin = new_admin;
+        emit!(AdminProposed {
+            admin: cfg.admin,
+            pending_admin: new_admin
+        });
+        Ok(())
+    }
+
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        let cfg = &mut ctx.accounts.config;
+        require!(
+            cfg.pending_admin != Pubkey::default(),
+            CreditError::NoPendingAdmin
+        );
+        require_keys_eq!(
+            ctx.accounts.new_admin.key(),
+            cfg.pending_admin,
+            CreditError::Unauthorized
+        );
+        let old_admin = cfg.admin;
+        cfg.admin = cfg.pending_admin;
+        cfg.pending_admin = Pubkey::default();
+        emit!(AdminChanged {
+            old_admin,
+            new_admin: cfg.admin
+        });
+        Ok(())
+    }
+
+    /// Admin inclui, habilita ou desabilita uma stablecoin aceita (USDC, USDT, PYUSD, BRZ...)
+    /// e cria o cofre do protocolo nessa moeda. A cotação em USDC não é definida aqui: vem do
+    /// `PriceAccount` da moeda no collateral_vault (USDC fica em paridade).
+    pub fn set_settlement_mint(ctx: Context<SetSettlementMint>, enabled: bool) -> Result<()> {
+        let cfg = &mut ctx.accounts.config;
+        let mint = ctx.accounts.mint.key();
+        let entry = SettlementMint {
+            mint,
+            decimals: ctx.accounts.mint.decimals,
+            enabled,
+        };
+        let count = cfg.settlement_mint_count as usize;
+        match cfg.settlement_mints[..count]
+            .iter()
+            .position(|m| m.mint == mint)
+        {
+            Some(i) => cfg.settlement_mints[i] = entry,
+            None => {
+                require!(count < MAX_SETTLEMENT_MINTS, CreditError::TooManyMints);
+                cfg.settlement_mints[count] = entry;
+                cfg.settlement_mint_count += 1;
+            }
+        }
+        emit!(SettlementMintUpdated {
+            mint,
+            decimals: entry.decimals,
+            enabled
+        });
         Ok(())
     }
 
     pub fn open_account(ctx: Context<OpenAccount>) -> Result<()> {
+        let mint = ctx.accounts.mint.key();
+        require!(
+            ctx.accounts.config.settlement_mint(&mint)?.enabled,
+            CreditError::MintNotAllowed
+        );
         let acct = &mut ctx.accounts.credit_account;
         acct.owner = ctx.accounts.owner.key();
-        acct.limit_usdc = 0;
-        acct.used_usdc = 0;
+        acct.mint = mint;
+        acct.limit_amount = 0;
+        acct.used_amount = 0;
         acct.health_factor_bps = 12000; // 1.20
         acct.score = 0;
         acct.billing_cycle_day = 5;
         acct.status = AccountStatus::Active as u8;
+        let now = Clock::get()?.unix_timestamp;
+        acct.opened_ts = now;
+        acct.next_statement_ts = next_billing_ts(now, acct.billing_cycle_day);
         acct.bump = ctx.bumps.credit_account;
         Ok(())
     }
 
-    pub fn set_limit(ctx: Context<SetLimit>, new_limit_usdc: u64) -> Result<()> {
+    /// Cria um cartão adicional: `key` passa a assinar compras pela conta, dentro dos
+    /// próprios limites (PDA `[b"delegate", credit_account, key]`).
+    pub fn add_delegate(
+        ctx: Context<AddDelegate>,
+        key: Pubkey,
+        params: DelegateParams,
+    ) -> Result<()> {
+        let d = &mut ctx.accounts.delegate;
+        d.credit_account = ctx.accounts.credit_account.key();
+        d.key = key;
+        d.spent_amount = 0;
+        d.bump = ctx.bumps.delegate;
+        d.apply(&params)?;
+        let acct = &mut ctx.accounts.credit_account;
+        acct.delegate_count = acct.delegate_count.saturating_add(1);
+        emit!(DelegateUpdated {
+            owner: ctx.accounts.owner.key(),
+            key,
+            spend_limit_amount: params.spend_limit_amount,
+            per_tx_max_amount: params.per_tx_max_amount,
+            expires_ts: params.expires_ts
+        });
+        Ok(())
+    }
+
+    /// Altera limites, validade e lojistas permitidos; o gasto acumulado é mantido.
+    pub fn update_delegate(ctx: Context<UpdateDelegate>, params: DelegateParams) -> Result<()> {
+        let d = &mut ctx.accounts.delegate;
+        d.apply(&params)?;
+        emit!(DelegateUpdated {
+            owner: ctx.accounts.owner.key(),
+            key: d.key,
+            spend_limit_amount: params.spend_limit_amount,
+            per_tx_max_amount: params.per_tx_max_amount,
+            expires_ts: params.expires_ts
+        });
+        Ok(())
+    }
+
+    /// Revoga o cartão adicional a qualquer momento; o rent volta ao owner.
+    pub fn revoke_delegate(ctx: Context<UpdateDelegate>) -> Result<()> {
+        let acct = &mut ctx.accounts.credit_account;
+        acct.delegate_count = acct.delegate_count.saturating_sub(1);
+        emit!(DelegateRevoked {
+            owner: ctx.accounts.owner.key(),
+            key: ctx.accounts.delegate.key
+        });
+        ctx.accounts
+            .delegate
+            .close(ctx.accounts.owner.to_account_info())
+    }
+
+    /// Define os controles de gasto da conta (janelas de 24h/7 dias, compras por dia,
+    /// teto por compra e categorias bloqueadas). Valores 0 desligam cada controle.
+    pub fn set_spend_controls(
+        ctx: Context<SetSpendControls>,
+        params: SpendControlsParams,
+    ) -> Result<()> {
+        let c = &mut ctx.accounts.spend_controls;
+        c.owner = ctx.accounts.owner.key();
+        c.daily_limit_amount = params.daily_limit_amount;
+        c.weekly_limit_amount = params.weekly_limit_amount;
+        c.max_tx_per_day = params.max_tx_per_day;
+        c.per_tx_max_amount = params.per_tx_max_amount;
+        c.blocked_categories = params.blocked_categories;
+        c.bump = ctx.bumps.spend_controls;
+        ctx.accounts.credit_account.has_spend_controls = true;
+        emit!(SpendControlsUpdated {
+            owner: c.owner,
+            params
+        });
+        Ok(())
+    }
+
+    /// Remove os controles de gasto e devolve o rent ao owner.
+    pub fn clear_spend_controls(ctx: Context<ClearSpendControls>) -> Result<()> {
+        ctx.accounts.credit_account.has_spend_controls = false;
+        emit!(SpendControlsUpdated {
+            owner: ctx.accounts.owner.key(),
+            params: SpendControlsParams::default()
+        });
+        Ok(())
+    }
+
+    /// Admin cadastra a categoria do lojista (0..32), usada pelos controles de gasto.
+    pub fn set_merchant_category(ctx: Context<SetMerchantCategory>, category: u8) -> Result<()> {
+        require!(category < 32, CreditError::InvalidCategory);
+        let profile = &mut ctx.accounts.merchant_profile;
+        profile.merchant = ctx.accounts.merchant.key();
+        profile.category = category;
+        profile.bump = ctx.bumps.merchant_profile;
+        emit!(MerchantCategorySet {
+            merchant: profile.merchant,
+            category
+        });
+        Ok(())
+    }
+
+    pub fn set_limit(ctx: Context<SetLimit>, new_limit_amount: u64) -> Result<()> {
+        let acct = &mut ctx.accounts.credit_account;
+        require!(
+            ctx.accounts.admin.key() == ctx.accounts.config.admin,
+            CreditError::Unauthorized
+        );
+        require!(!acct.auto_limit, CreditError::AutoLimitEnabled);
+        require!(acct.used_amount <= new_limit_amount, CreditError::UsedExceedsNewLimit);
+        acct.limit_amount = new_limit_amount;
+        Ok(())
+    }
+
+    /// Admin define o sublimite de saque em dinheiro da conta.
+    pub fn set_cash_limit(ctx: Context<SetLimit>, cash_limit_amount: u64) -> Result<()> {
+        require!(
+            ctx.accounts.admin.key() == ctx.accounts.config.admin,
+            CreditError::Unauthorized
+        );
         let acct = &mut ctx.accounts.credit_account;
+        acct.cash_limit_amount = cash_limit_amount;
+        Ok(())
+    }
+
+    /// Liga/desliga o limite automático (`refresh_limit`) e define o teto por conta.
+    pub fn set_limit_mode(
+        ctx: Context<SetLimit>,
+        auto_limit: bool,
+        limit_ceiling_amount: u64,
+    ) -> Result<()> {
         require!(
             ctx.accounts.admin.key() == ctx.accounts.config.admin,
             CreditError::Unauthorized
         );
-        require!(acct.used_usdc <= new_limit_usdc, CreditError::UsedExceedsNewLimit);
-        acct.limit_usdc = new_limit_usdc;
+        let acct = &mut ctx.accounts.credit_account;
+        acct.auto_limit = auto_limit;
+        acct.limit_ceiling_amount = limit_ceiling_amount;
+        Ok(())
+    }
+
+    /// Permissionless: recalcula o limite de contas em modo automático como
+    /// Σ quantidade × preço atual × `ltv_bps` das posições, limitado pelo teto da conta e pela
+    /// faixa de score. `remaining_accounts`: mesmo layout de `refresh_health`.
+    pub fn refresh_limit<'info>(
+        ctx: Context<'_, '_, 'info, 'info, AccountCrank<'info>>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let cfg = &ctx.accounts.config;
+        let acct = &mut ctx.accounts.credit_account;
+        require!(acct.auto_limit, CreditError::AutoLimitDisabled);
+
+        // Colateral e tetos por faixa estão em USDC; o limite, na moeda da conta
+        let settlement =
+            cfg.settlement_rate(&acct.mint, price_of(&ctx.accounts.settlement_price), now)?;
+        let collateral_credit_usdc =
+            value_collateral(&acct.owner, ctx.remaining_accounts, cfg, now)?.weighted_usdc;
+        let band = score_band(acct.score);
+        let mut new_limit = settlement
+            .from_usdc(collateral_credit_usdc)
+            .min(acct.limit_ceiling_amount);
+        let tier_cap = cfg.score_tier_caps_usdc[band as usize];
+        if tier_cap > 0 {
+            new_limit = new_limit.min(settlement.from_usdc(tier_cap));
+        }
+
+        let old_limit_amount = acct.limit_amount;
+        acct.limit_amount = new_limit;
+        emit!(LimitUpdated {
+            owner: acct.owner,
+            old_limit_amount,
+            new_limit_amount: new_limit,
+            collateral_credit_usdc,
+            score_band: band
+        });
         Ok(())
     }
 
-    pub fn charge(
-        ctx: Context<Charge>,
-        amount_usdc: u64,
+    /// Autoriza a compra e emite atomicamente, via CPI no `receivables`, uma nota por parcela.
+    /// `remaining_accounts`: os PDAs `[b"note", order_id, index]` (ainda não criados), em ordem.
+    pub fn charge<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Charge<'info>>,
+        amount: u64,
         installments: u8,
         order_id: [u8; 32],
+        plan: u8,
     ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
         let acct = &mut ctx.accounts.credit_account;
         let cfg = &ctx.accounts.config;
+        acct.accrue(cfg, now);
+
+        // Compra precisa do portador (owner ou cartão adicional) e do lojista
+        let authority = ctx.accounts.authority.key();
+        if authority != acct.owner {
+            let delegate = ctx
+                .accounts
+                .delegate
+                .as_mut()
+                .ok_or(CreditError::Unauthorized)?;
+            delegate.authorize(amount, &ctx.accounts.merchant.key(), now)?;
+        }
+        // Controles de gasto do owner valem para qualquer portador
+        if acct.has_spend_controls {
+            let controls = ctx
+                .accounts
+                .spend_controls
+                .as_mut()
+                .ok_or(CreditError::SpendControlsMissing)?;
+            // Categoria vem do cadastro do lojista feito pelo admin, não de quem chama
+            let category = ctx.accounts.merchant_profile.as_ref().map(|p| p.category);
+            controls.authorize(amount, category, now)?;
+        }
 
         require!(acct.status == AccountStatus::Active as u8, CreditError::AccountFrozen);
-        let available = acct.limit_usdc.saturating_sub(acct.used_usdc);
-        require!(available >= amount_usdc, CreditError::InsufficientLimit);
-        require!(acct.health_factor_bps >= cfg.min_hf_bps_for_new_charges, CreditError::HfTooLow);
-        require!(installments > 0, CreditError::InstallmentsNotAllowed);
+        require!(
+            cfg.settlement_mint(&acct.mint)?.enabled,
+            CreditError::MintNotAllowed
+        );
+        require!(
+            cfg.max_price_age_secs == 0 || now - acct.last_health_ts <= cfg.max_price_age_secs,
+            CreditError::StaleHealthFactor
+        );
+        require!(
+            installments > 0 && installments as usize <= MAX_INSTALLMENTS,
+            CreditError::InstallmentsNotAllowed
+        );
+        require!(
+            ctx.remaining_accounts.len() == installments as usize,
+            CreditError::NoteAccountsMismatch
+        );
+
+        // Parcelas: lojista absorve o custo (comprador paga parcelas iguais sem juros) ou
+        // comprador paga juros pela tabela Price com a taxa mensal do Config
+        let schedule = match plan {
+            p if p == InstallmentPlan::MerchantAbsorbed as u8 => {
+                split_installments(amount, installments)
+            }
+            p if p == InstallmentPlan::BuyerPaid as u8 => {
+                price_table_schedule(amount, cfg.installment_rate_bps_monthly, installments)
+                    .ok_or(CreditError::MathOverflow)?
+            }
+            _ => return err!(CreditError::InvalidPlan),
+        };
+        // Valor líquido de cada nota para o lojista: no plano absorvido, a parcela descontada
+        // a valor presente pela taxa do lojista; no plano do comprador, só o principal
+        let mut note_net_amount = [0u64; MAX_INSTALLMENTS];
+        for (i, net) in note_net_amount
+            .iter_mut()
+            .take(installments as usize)
+            .enumerate()
+        {
+            *net = if plan == InstallmentPlan::MerchantAbsorbed as u8 {
+                present_value(
+                    schedule[i].total_amount(),
+                    cfg.merchant_discount_rate_bps_monthly,
+                    i as u32 + 1,
+                )
+                .ok_or(CreditError::MathOverflow)?
+            } else {
+                schedule[i].principal_amount
+            };
+        }
+        let merchant_net_amount: u64 = note_net_amount.iter().sum();
+        let interest_amount: u64 = schedule.iter().map(|p| p.interest_amount).sum();
+        let financed_amount = amount
+            .checked_add(interest_amount)
+            .ok_or(CreditError::MathOverflow)?;
+
+        // O limite consome o total financiado (principal + juros das parcelas)
+        let available = acct.limit_amount.saturating_sub(acct.used_amount);
+        require!(available >= financed_amount, CreditError::InsufficientLimit);
+        // HF já com a compra somada à dívida, sobre o colateral da última avaliação
+        let settlement =
+            cfg.settlement_rate(&acct.mint, price_of(&ctx.accounts.settlement_price), now)?;
+        require!(
+            acct.projected_health_factor_bps(&settlement, financed_amount)
+                >= cfg.min_hf_bps_for_new_charges,
+            CreditError::HfTooLow
+        );
+
+        // Contábil: cada parcela cai em uma fatura futura
+        acct.used_amount = acct.used_amount.saturating_add(financed_amount);
+        for (slot, part) in acct.schedule_amount.iter_mut().zip(schedule.iter()) {
+            *slot = slot.saturating_add(part.total_amount());
+        }
+        acct.apply_credit_balance();
+
+        // Recebíveis: parcela i vence na data de vencimento da fatura que a cobra
+        let seeds: &[&[u8]] = &[b"credit_vault", &[cfg.vault_authority_bump]];
+        let signer = &[seeds];
+        let mut due_ts = acct.next_statement_ts;
+        for (i, note) in ctx.remaining_accounts.iter().enumerate() {
+            due_ts = next_billing_ts(due_ts, acct.billing_cycle_day);
+            let cpi_accounts = receivables::cpi::accounts::MintNote {
+                issuer: ctx.accounts.vault_authority.to_account_info(),
+                payer: ctx.accounts.merchant.to_account_info(),
+                note_state: note.clone(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.receivables_program.to_account_info(),
+                cpi_accounts,
+                signer,
+            );
+            receivables::cpi::mint_note(
+                cpi_ctx,
+                order_id,
+                i as u8,
+                receivables::NoteParams {
+                    buyer: acct.owner,
+                    merchant: ctx.accounts.merchant.key(),
+                    amount: schedule[i].total_amount(),
+                    due_ts,
+                    net_amount: note_net_amount[i],
+                    plan,
+                    mint: acct.mint,
+                },
+            )?;
+            emit!(InstallmentScheduled {
+                order_id,
+                index: i as u8,
+                principal_amount: schedule[i].principal_amount,
+                interest_amount: schedule[i].interest_amount,
+                due_ts
+            });
+        }
 
-        // Contábil
-        acct.used_usdc = acct.used_usdc.saturating_add(amount_usdc);
+        // Registro canônico do pedido: o `init` do PDA impede cobrar o mesmo order_id duas vezes
+        let order = &mut ctx.accounts.order_record;
+        order.order_id = order_id;
+        order.owner = acct.owner;
+        order.merchant = ctx.accounts.merchant.key();
+        order.mint = acct.mint;
+        order.authority = authority;
+        order.amount = amount;
+        order.interest_amount = interest_amount;
+        order.merchant_net_amount = merchant_net_amount;
+        order.installments = installments;
+        order.plan = plan;
+        order.first_cycle = acct.cycle_count + 1;
+        order.created_ts = now;
+        order.status = OrderStatus::Open as u8;
+        order.refunded_amount = 0;
+        order.disputed_ts = 0;
+        order.dispute_deadline_ts = 0;
+        order.disputed_notes_amount = [0; MAX_INSTALLMENTS];
+        order.merchant_debt_amount = 0;
+        order.bump = ctx.bumps.order_record;
 
-        // Evento: indexador off-chain observará e criará as N notas chamando receivables::mint_note
         emit!(ChargeAuthorized {
             owner: acct.owner,
-            amount_usdc,
+            merchant: ctx.accounts.merchant.key(),
+            authority,
+            amount,
+            interest_amount,
+            merchant_net_amount,
             installments,
+            plan,
             order_id
         });
         Ok(())
     }
 
-    pub fn repay(ctx: Context<Repay>, amount_usdc: u64) -> Result<()> {
+    /// Estorno total ou parcial de uma compra, assinado pelo lojista. O valor sai das parcelas
+    /// da última para a primeira: reduz/cancela as notas, libera o limite e abate o saldo da
+    /// parcela (futura, fatura ou vencida); o que o comprador já pagou vira crédito na conta.
+    /// Notas antecipadas são recompradas: o lojista devolve o líquido proporcional ao beneficiário.
+    /// `remaining_accounts`: pares `[nota, token account do beneficiário na moeda da conta]`,
+    /// da última parcela para a primeira, até cobrir o valor.
+    pub fn refund<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Refund<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        require!(amount > 0, CreditError::InvalidAmount);
+        let now = Clock::get()?.unix_timestamp;
+        let cfg = &ctx.accounts.config;
         let acct = &mut ctx.accounts.credit_account;
-        acct.used_usdc = acct.used_usdc.saturating_sub(amount_usdc);
-        if acct.status == AccountStatus::SoftFrozen as u8 && acct.used_usdc == 0 {
-            acct.status = AccountStatus::Active as u8;
+        let order = &mut ctx.accounts.order_record;
+        acct.accrue(cfg, now);
+        require!(
+            order.status != OrderStatus::Disputed as u8,
+            CreditError::OrderDisputed
+        );
+
+        let financed_amount = order.financed_amount();
+        require!(
+            amount <= financed_amount.saturating_sub(order.refunded_amount),
+            CreditError::RefundExceedsOrder
+        );
+        let pairs = ctx.remaining_accounts.chunks_exact(2);
+        require!(
+            pairs.remainder().is_empty(),
+            CreditError::NoteAccountsMismatch
+        );
+
+        let seeds: &[&[u8]] = &[b"credit_vault", &[cfg.vault_authority_bump]];
+        let signer = &[seeds];
+        let mut left = amount;
+        let mut credit_amount = 0u64;
+        let mut clawback_amount = 0u64;
+        let mut index = order.installments;
+        for pair in pairs {
+            if left == 0 {
+                break;
+            }
+            require!(index > 0, CreditError::NoteAccountsMismatch);
+            index -= 1;
+            let (note_info, beneficiary_info) = (&pair[0], &pair[1]);
+            let note = load_order_note(&order.order_id, index, note_info)?;
+            // Nota já cancelada: segue para a parcela anterior
+            let cut = left.min(note.amount);
+            if cut == 0 {
+                continue;
+            }
+            let net_cut = receivables::net_reduction(note.net_amount, note.amount, cut);
+            let beneficiary = note.beneficiary;
+
+            let cpi_accounts = receivables::cpi::accounts::ReduceNote {
+                issuer: ctx.accounts.vault_authority.to_account_info(),
+                note_state: note_info.clone(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.receivables_program.to_account_info(),
+                cpi_accounts,
+                signer,
+            );
+            receivables::cpi::reduce_note(cpi_ctx, cut)?;
+
+            // Nota antecipada: o beneficiário pagou o líquido ao lojista, que o devolve
+            if beneficiary != order.merchant && net_cut > 0 {
+                let cpi_accounts = spl_token::TransferChecked {
+                    from: ctx.accounts.merchant_ata.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: beneficiary_info.clone(),
+                    authority: ctx.accounts.merchant.to_account_info(),
+                };
+                let cpi_ctx =
+                    CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+                buy_back_note(
+                    cpi_ctx,
+                    &beneficiary,
+                    beneficiary_info,
+                    &ctx.accounts.mint,
+                    net_cut,
+                )?;
+                clawback_amount += net_cut;
+            }
+
+            credit_amount += acct.reverse_installment(order.first_cycle + index as u32, cut);
+            left -= cut;
         }
-        emit!(PaymentPosted { owner: acct.owner, amount_usdc });
-        Ok(())
-    }
+        require!(left == 0, CreditError::NoteAccountsMismatch);
 
-    pub fn statement_close(ctx: Context<StatementClose>, cycle_id: [u8; 8]) -> Result<()> {
-        let acct = &ctx.accounts.credit_account;
-        emit!(StatementClosed {
-            owner: acct.owner,
-            cycle_id,
-            total_due_usdc: 0,
-            min_payment_usdc: 0,
-            due_date_ts: 0
+        // Parcelas já pagas voltam como crédito e abatem o que ainda estiver em aberto
+        acct.credit_balance_amount = acct.credit_balance_amount.saturating_add(credit_amount);
+        acct.apply_credit_balance();
+
+        let refunded_before_amount = order.refunded_amount;
+        order.refunded_amount += amount;
+        restore_delegate_spend(&ctx.accounts.delegate, order, refunded_before_amount)?;
+        order.status = if order.refunded_amount == financed_amount {
+            OrderStatus::Refunded as u8
+        } else {
+            OrderStatus::PartiallyRefunded as u8
+        };
+
+        emit!(ChargeRefunded {
+            order_id: order.order_id,
+            owner: order.owner,
+            merchant: order.merchant,
+            amount,
+            refunded_total_amount: order.refunded_amount,
+            clawback_amount,
+            credit_amount,
+            ts: now
         });
         Ok(())
     }
 
-    pub fn soft_freeze(ctx: Context<Freeze>) -> Result<()> {
-        ctx.accounts.credit_account.status = AccountStatus::SoftFrozen as u8;
-        Ok(())
-    }
+    /// Comprador contesta a compra dentro de `dispute_window_days`. O saldo ainda devido das
+    /// parcelas sai do cronograma/fatura/vencido para `disputed_amount`: não entra no mínimo
+    /// nem gera mora até a decisão. As notas ficam marcadas (não podem ser antecipadas).
+    /// `remaining_accounts`: todas as notas do pedido, na ordem dos índices.
+    pub fn open_dispute<'info>(
+        ctx: Context<'_, '_, 'info, 'info, OpenDispute<'info>>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let cfg = &ctx.accounts.config;
+        let acct = &mut ctx.accounts.credit_account;
+        let order = &mut ctx.accounts.order_record;
+        require!(order.disputed_ts == 0, CreditError::DisputeAlreadyOpened);
+        require!(
+            order.plan != InstallmentPlan::Restructure as u8,
+            CreditError::InvalidPlan
+        );
+        require!(
+            order.status != OrderStatus::Refunded as u8,
+            CreditError::OrderRefunded
+        );
+        require!(
+            now <= order.created_ts + cfg.dispute_window_days as i64 * SECONDS_PER_DAY,
+            CreditError::DisputeWindowClosed
+        );
+        require!(
+            ctx.remaining_accounts.len() == order.installments as usize,
+            CreditError::NoteAccountsMismatch
+        );
+        acct.accrue(cfg, now);
+        let floor_amount = cfg
+            .settlement_quote(&acct.mint, price_of(&ctx.accounts.settlement_price))?
+            .from_usdc(cfg.min_payment_floor_usdc);
+
+        let seeds: &[&[u8]] = &[b"credit_vault", &[cfg.vault_authority_bump]];
+        let signer = &[seeds];
+        let mut disputed_amount = 0u64;
+        for (i, note_info) in ctx.remaining_accounts.iter().enumerate() {
+            let note = load_order_note(&order.order_id, i as u8, note_info)?;
+            let held = acct.hold_installment(
+                cfg,
+                floor_amount,
+                order.first_cycle + i as u32,
+                note.amount,
+            );
+            order.disputed_notes_amount[i] = held;
+            disputed_amount += held;
+
+            let cpi_accounts = receivables::cpi::accounts::SetNoteDisputed {
+                issuer: ctx.accounts.vault_authority.to_account_info(),
+                note_state: note_info.clone(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.receivables_program.to_account_info(),
+                cpi_accounts,
+                signer,
+            );
+            receivables::cpi::set_note_disputed(cpi_ctx, true)?;
+        }
 
-    pub fn hard_freeze(ctx: Context<Freeze>) -> Result<()> {
-        ctx.accounts.credit_account.status = AccountStatus::HardFrozen as u8;
+        let deadline_ts = now + cfg.dispute_resolution_days as i64 * SECONDS_PER_DAY;
+        order.status = OrderStatus::Disputed as u8;
+        order.disputed_ts = now;
+        order.dispute_deadline_ts = deadline_ts;
+        acct.open_disputes = acct.open_disputes.saturating_add(1);
+
+        emit!(DisputeOpened {
+            order_id: order.order_id,
+            owner: order.owner,
+            merchant: order.merchant,
+            disputed_amount,
+            deadline_ts
+        });
         Ok(())
     }
-}
 
-// ---------------- Accounts ----------------
-#[account]
-pub struct CreditAccount {
-    pub owner: Pubkey,
-    pub limit_usdc: u64,
-    pub used_usdc: u64,
-    pub health_factor_bps: u32,
-    pub score: u16,
-    pub billing_cycle_day: u8,
-    pub status: u8,
-    pub bump: u8,
-}
+    /// Decide a disputa. Até o prazo, só o admin decide; depois dele qualquer um pode
+    /// executar e o comprador vence. Comprador vence: as notas são canceladas como num
+    /// estorno total (o já pago vira crédito); as antecipadas pelo pool configurado são
+    /// recompradas pelo cofre do protocolo e o valor fica em `merchant_debt_amount` do pedido.
+    /// Lojista vence: as parcelas voltam aos saldos de origem. O mínimo da fatura acompanha o
+    /// que volta a ser exigível.
+    /// `remaining_accounts`: pares `[nota, token account do beneficiário na moeda da conta]`
+    /// de todas as parcelas, na ordem dos índices.
+    pub fn resolve_dispute<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ResolveDispute<'info>>,
+        outcome: u8,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let cfg = &ctx.accounts.config;
+        let acct = &mut ctx.accounts.credit_account;
+        let order = &mut ctx.accounts.order_record;
+        require!(
+            order.status == OrderStatus::Disputed as u8,
+            CreditError::NotDisputed
+        );
+        let resolver = ctx.accounts.resolver.key();
+        let buyer_wins = if now < order.dispute_deadline_ts {
+            require_keys_eq!(resolver, cfg.admin, CreditError::Unauthorized);
+            match outcome {
+                o if o == DisputeOutcome::BuyerWins as u8 => true,
+                o if o == DisputeOutcome::MerchantWins as u8 => false,
+                _ => return err!(CreditError::InvalidOutcome),
+            }
+        } else {
+            true
+        };
+        let pairs = ctx.remaining_accounts.chunks_exact(2);
+        require!(
+            pairs.remainder().is_empty() && pairs.len() == order.installments as usize,
+            CreditError::NoteAccountsMismatch
+        );
+        acct.accrue(cfg, now);
+        let floor_amount = cfg
+            .settlement_quote(&acct.mint, price_of(&ctx.accounts.settlement_price))?
+            .from_usdc(cfg.min_payment_floor_usdc);
 
-#[account]
-pub struct Config {
-    pub min_hf_bps_for_new_charges: u32,
-    pub min_hf_bps_for_withdraw: u32,
-    pub penalty_rate_bps_daily: u32,
-    pub late_fee_bps: u32,
-    pub grace_volatile_days: u8,
-    pub grace_any_days: u8,
-    pub admin: Pubkey,
-    pub bump: u8,
-}
+        let seeds: &[&[u8]] = &[b"credit_vault", &[cfg.vault_authority_bump]];
+        let signer = &[seeds];
+        let mut released_amount = 0u64;
+        let mut credit_amount = 0u64;
+        let mut buyback_amount = 0u64;
+        for (i, pair) in pairs.enumerate() {
+            let (note_info, beneficiary_info) = (&pair[0], &pair[1]);
+            let note = load_order_note(&order.order_id, i as u8, note_info)?;
+            let (amount, net_amount) = (note.amount, note.net_amount);
+            let beneficiary = note.beneficiary;
+            let advanced = advanced_by_pool(cfg, &note);
+            let held = std::mem::take(&mut order.disputed_notes_amount[i]);
+            let cycle = order.first_cycle + i as u32;
+            released_amount += held;
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
-pub struct ConfigParams {
-    pub min_hf_bps_for_new_charges: u32,
-    pub min_hf_bps_for_withdraw: u32,
-    pub penalty_rate_bps_daily: u32,
-    pub late_fee_bps: u32,
-    pub grace_volatile_days: u8,
-    pub grace_any_days: u8,
-}
+            let cpi_accounts = receivables::cpi::accounts::SetNoteDisputed {
+                issuer: ctx.accounts.vault_authority.to_account_info(),
+                note_state: note_info.clone(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.receivables_program.to_account_info(),
+                cpi_accounts,
+                signer,
+            );
+            receivables::cpi::set_note_disputed(cpi_ctx, false)?;
 
-// ---------------- Contexts ----------------
-#[derive(Accounts)]
-pub struct InitConfig<'info> {
-    #[account(mut)]
-    pub admin: Signer<'info>,
-    #[account(
-        init,
-        payer = admin,
-        space = 8 + std::mem::size_of::<Config>(),
-        seeds = [b"credit_config"],
-        bump
-    )]
-    pub config: Account<'info, Config>,
-    pub system_program: Program<'info, System>,
-}
+            if !buyer_wins {
+                acct.release_installment(cfg, floor_amount, cycle, held);
+                continue;
+            }
+            acct.drop_held_installment(held);
+            credit_amount += amount - held.min(amount);
+            if amount > 0 {
+                // Nota antecipada pelo pool: o cofre recompra e o lojista fica devendo.
+                // Nota cedida por outra via só é cancelada
+                if advanced && net_amount > 0 {
+                    let cpi_accounts = spl_token::TransferChecked {
+                        from: ctx.accounts.vault.to_account_info(),
+                        mint: ctx.accounts.mint.to_account_info(),
+                        to: beneficiary_info.clone(),
+                        authority: ctx.accounts.vault_authority.to_account_info(),
+                    };
+                    let cpi_ctx = CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        cpi_accounts,
+                        signer,
+                    );
+                    buy_back_note(
+                        cpi_ctx,
+                        &beneficiary,
+                        beneficiary_info,
+                        &ctx.accounts.mint,
+                        net_amount,
+                    )?;
+                    buyback_amount += net_amount;
+                }
+                let cpi_accounts = receivables::cpi::accounts::ReduceNote {
+                    issuer: ctx.accounts.vault_authority.to_account_info(),
+                    note_state: note_info.clone(),
+                };
+                let cpi_ctx = CpiContext::new_with_signer(
+                    ctx.accounts.receivables_program.to_account_info(),
+                    cpi_accounts,
+                    signer,
+                );
+                receivables::cpi::reduce_note(cpi_ctx, amount)?;
+            }
+        }
 
-#[derive(Accounts)]
-pub struct OpenAccount<'info> {
-    #[account(mut)]
-    pub owner: Signer<'info>,
-    #[account(
-        init,
-        payer = owner,
-        space = 8 + std::mem::size_of::<CreditAccount>(),
-        seeds = [b"credit", owner.key().as_ref()],
-        bump
-    )]
-    pub credit_account: Account<'info, CreditAccount>,
-    pub system_program: Program<'info, System>,
-}
+        if buyer_wins {
+            acct.credit_balance_amount = acct.credit_balance_amount.saturating_add(credit_amount);
+            acct.apply_credit_balance();
+            let refunded_before_amount = order.refunded_amount;
+            order.refunded_amount = order.financed_amount();
+            restore_delegate_spend(&ctx.accounts.delegate, order, refunded_before_amount)?;
+            order.merchant_debt_amount = order.merchant_debt_amount.saturating_add(buyback_amount);
+            order.status = OrderStatus::Refunded as u8;
+        } else if order.refunded_amount > 0 {
+            order.status = OrderStatus::PartiallyRefunded as u8;
+        } else {
+            order.status = OrderStatus::Open as u8;
+        }
+        acct.open_disputes = acct.open_disputes.saturating_sub(1);
 
-#[derive(Accounts)]
-pub struct SetLimit<'info> {
-    #[account(mut)]
-    pub admin: Signer<'info>,
-    pub config: Account<'info, Config>,
-    #[account(mut, has_one = owner)]
-    pub credit_account: Account<'info, CreditAccount>,
-    /// CHECK: only used for has_one check
-    pub owner: UncheckedAccount<'info>,
-}
+        emit!(DisputeResolved {
+            order_id: order.order_id,
+            owner: order.owner,
+            resolver,
+            buyer_wins,
+            released_amount,
+            credit_amount,
+            buyback_amount,
+            ts: now
+        });
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct Charge<'info> {
-    #[account(mut)]
-    pub credit_account: Account<'info, CreditAccount>,
-    pub config: Account<'info, Config>,
-}
+    /// Lojista devolve ao cofre o líquido de notas antecipadas recompradas numa disputa perdida.
+    pub fn repay_merchant_debt(ctx: Context<RepayMerchantDebt>, amount: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order_record;
+        require!(
+            amount > 0 && amount <= order.merchant_debt_amount,
+            CreditError::InvalidAmount
+        );
+        let cpi_accounts = spl_token::TransferChecked {
+            from: ctx.accounts.merchant_ata.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.vault.to_account_info(),
+            authority: ctx.accounts.merchant.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        spl_token::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)?;
+        order.merchant_debt_amount -= amount;
 
-#[derive(Accounts)]
-pub struct Repay<'info> {
-    #[account(mut)]
-    pub credit_account: Account<'info, CreditAccount>,
-}
+        emit!(MerchantDebtRepaid {
+            order_id: order.order_id,
+            merchant: order.merchant,
+            amount,
+            remaining_amount: order.merchant_debt_amount,
+        });
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct StatementClose<'info> {
-    pub credit_account: Account<'info, CreditAccount>,
-}
+    pub fn repay(ctx: Context<Repay>, amount: u64) -> Result<()> {
+        require!(amount > 0, CreditError::InvalidAmount);
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts
+            .credit_account
+            .accrue(&ctx.accounts.config, now);
+        require!(
+            amount <= ctx.accounts.credit_account.payable_amount(),
+            CreditError::Overpayment
+        );
 
-#[derive(Accounts)]
-pub struct Freeze<'info> {
+        // payer transfere a moeda da conta do seu ATA para o cofre do protocolo
+        let cpi_accounts = spl_token::TransferChecked {
+            from: ctx.accounts.payer_ata.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.vault.to_account_info(),
+            authority: ctx.accounts.payer.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        spl_token::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)?;
+
+        let acct = &mut ctx.accounts.credit_account;
+        acct.post_repayment(amount, now);
+        emit!(PaymentPosted {
+            owner: acct.owner,
+            payer: ctx.accounts.payer.key(),
+            amount,
+            remaining_used_amount: acct.used_amount,
+            ts: now
+        });
+        Ok(())
+    }
+
+    /// Saque em dinheiro: o pool de antecipação transfere a moeda da conta ao ATA do owner (CPI
+    /// `advance_pool::disburse` assinada pelo PDA `credit_vault`). Consome o limite total e
+    /// o sublimite de saque, exige HF recente e maior que compras e paga juros diários
+    /// desde o saque. O principal pago volta ao pool por `return_cash_to_pool`.
+    pub fn cash_advance(ctx: Context<CashAdvance>, amount: u64) -> Result<()> {
+        require!(amount > 0, CreditError::InvalidAmount);
+        let now = Clock::get()?.unix_timestamp;
+        let cfg = &ctx.accounts.config;
+        let acct = &mut ctx.accounts.credit_account;
+        acct.accrue(cfg, now);
+        require!(
+            acct.status == AccountStatus::Active as u8,
+            CreditError::AccountFrozen
+        );
+        require!(
+            cfg.settlement_mint(&acct.mint)?.enabled,
+            CreditError::MintNotAllowed
+        );
+        let settlement =
+            cfg.settlement_rate(&acct.mint, price_of(&ctx.accounts.settlement_price), now)?;
+        require!(
+            cfg.max_price_age_secs == 0 || now - acct.last_health_ts <= cfg.max_price_age_secs,
+            CreditError::StaleHealthFactor
+        );
+        // HF já com o saque e a tarifa do primeiro dia somados à dívida
+        let fee_amount = bps_of(amount, cfg.cash_rate_bps_daily);
+        require!(
+            acct.projected_health_factor_bps(&settlement, amount.saturating_add(fee_amount))
+                >= cfg.min_hf_bps_for_cash,
+            CreditError::HfTooLow
+        );
+        let cash_balance_amount = acct
+            .cash_balance_amount
+            .checked_add(amount)
+            .ok_or(CreditError::MathOverflow)?;
+        require!(
+            cash_balance_amount <= acct.cash_limit_amount,
+            CreditError::CashLimitExceeded
+        );
+        let available = acct.limit_amount.saturating_sub(acct.used_amount);
+        require!(available >= amount, CreditError::InsufficientLimit);
+
+        let seeds: &[&[u8]] = &[b"credit_vault", &[cfg.vault_authority_bump]];
+        let signer = &[seeds];
+        let cpi_accounts = advance_pool::cpi::accounts::Disburse {
+            credit_authority: ctx.accounts.vault_authority.to_account_info(),
+            pool: ctx.accounts.pool.to_account_info(),
+            vault: ctx.accounts.pool_vault.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            recipient: ctx.accounts.owner_ata.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.advance_pool_program.to_account_info(),
+            cpi_accounts,
+            signer,
+        );
+        advance_pool::cpi::disburse(cpi_ctx, amount)?;
+
+        acct.cash_balance_amount = cash_balance_amount;
+        acct.used_amount = acct.used_amount.saturating_add(amount);
+        emit!(CashAdvanced {
+            owner: acct.owner,
+            amount,
+            cash_balance_amount,
+            ts: now
+        });
+        Ok(())
+    }
+
+    /// Crank permissionless: repassa do cofre do protocolo ao pool o principal de saques em
+    /// dinheiro já pago pelo owner (`cash_repaid_amount`); o pool baixa `disbursed_amount`.
+    pub fn return_cash_to_pool(ctx: Context<ReturnCashToPool>) -> Result<()> {
+        let cfg = &ctx.accounts.config;
+        let acct = &mut ctx.accounts.credit_account;
+        let amount = std::mem::take(&mut acct.cash_repaid_amount);
+        require!(amount > 0, CreditError::NothingToReturn);
+
+        let seeds: &[&[u8]] = &[b"credit_vault", &[cfg.vault_authority_bump]];
+        let signer = &[seeds];
+        let cpi_accounts = advance_pool::cpi::accounts::RepayDisbursed {
+            credit_authority: ctx.accounts.vault_authority.to_account_info(),
+            pool: ctx.accounts.pool.to_account_info(),
+            vault: ctx.accounts.pool_vault.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            source: ctx.accounts.vault.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.advance_pool_program.to_account_info(),
+            cpi_accounts,
+            signer,
+        );
+        advance_pool::cpi::repay_disbursed(cpi_ctx, amount)?;
+
+        emit!(CashReturned {
+            owner: acct.owner,
+            pool: ctx.accounts.pool.key(),
+            amount
+        });
+        Ok(())
+    }
+
+    /// Ativa o débito automático: aprova o PDA `credit_vault` como delegate do ATA do owner
+    /// até `allowance_amount`. A cada fatura o crank puxa o mínimo ou o total da fatura
+    /// (`mode`), limitado a `cap_amount` por fatura (0 = sem teto).
+    pub fn enable_autopay(
+        ctx: Context<SetAutopay>,
+        mode: u8,
+        cap_amount: u64,
+        allowance_amount: u64,
+    ) -> Result<()> {
+        require!(
+            mode == AutopayMode::Minimum as u8 || mode == AutopayMode::Statement as u8,
+            CreditError::InvalidAutopayMode
+        );
+        let cpi_accounts = spl_token::Approve {
+            to: ctx.accounts.owner_ata.to_account_info(),
+            delegate: ctx.accounts.vault_authority.to_account_info(),
+            authority: ctx.accounts.owner.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        spl_token::approve(cpi_ctx, allowance_amount)?;
+
+        let acct = &mut ctx.accounts.credit_account;
+        acct.autopay_mode = mode;
+        acct.autopay_cap_amount = cap_amount;
+        emit!(AutopayUpdated {
+            owner: acct.owner,
+            mode,
+            cap_amount,
+            allowance_amount
+        });
+        Ok(())
+    }
+
+    /// Desativa o débito automático e revoga a aprovação no ATA.
+    pub fn disable_autopay(ctx: Context<SetAutopay>) -> Result<()> {
+        let cpi_accounts = spl_token::Revoke {
+            source: ctx.accounts.owner_ata.to_account_info(),
+            authority: ctx.accounts.owner.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        spl_token::revoke(cpi_ctx)?;
+
+        let acct = &mut ctx.accounts.credit_account;
+        acct.autopay_mode = AutopayMode::Off as u8;
+        acct.autopay_cap_amount = 0;
+        emit!(AutopayUpdated {
+            owner: acct.owner,
+            mode: AutopayMode::Off as u8,
+            cap_amount: 0,
+            allowance_amount: 0
+        });
+        Ok(())
+    }
+
+    /// Crank permissionless: a partir de `AUTOPAY_WINDOW_SECS` antes do vencimento, puxa do
+    /// ATA do owner o valor do modo escolhido (limitado ao teto por fatura, ao saldo e à
+    /// aprovação) e lança como pagamento. Débito parcial por falta de saldo ou aprovação pode
+    /// ser repetido; a fatura só conta como paga pelo débito automático ao atingir o alvo.
+    pub fn run_autopay(ctx: Context<RunAutopay>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let cfg = &ctx.accounts.config;
+        let acct = &mut ctx.accounts.credit_account;
+        require!(
+            acct.autopay_mode != AutopayMode::Off as u8,
+            CreditError::AutopayDisabled
+        );
+        require!(
+            acct.due_date_ts != 0 && now >= acct.due_date_ts - AUTOPAY_WINDOW_SECS,
+            CreditError::AutopayNotDue
+        );
+        require!(
+            acct.autopay_last_cycle != acct.cycle_count,
+            CreditError::AutopayAlreadyRun
+        );
+        acct.accrue(cfg, now);
+
+        let target_amount = if acct.autopay_mode == AutopayMode::Minimum as u8 {
+            acct.min_payment_due_amount
+        } else {
+            acct.statement_due_amount()
+        };
+        let ata = &ctx.accounts.owner_ata;
+        let allowance_amount = if ata.delegate == Some(ctx.accounts.vault_authority.key()).into() {
+            ata.delegated_amount
+        } else {
+            0
+        };
+        let cap_left_amount = if acct.autopay_cap_amount > 0 {
+            acct.autopay_cap_amount.saturating_sub(acct.autopay_paid_amount)
+        } else {
+            u64::MAX
+        };
+        let goal_amount = target_amount.min(acct.payable_amount()).min(cap_left_amount);
+        let amount = goal_amount.min(ata.amount).min(allowance_amount);
+        require!(amount > 0, CreditError::NothingToAutopay);
+
+        let seeds: &[&[u8]] = &[b"credit_vault", &[cfg.vault_authority_bump]];
+        let signer = &[seeds];
+        let cpi_accounts = spl_token::TransferChecked {
+            from: ctx.accounts.owner_ata.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.vault.to_account_info(),
+            authority: ctx.accounts.vault_authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            signer,
+        );
+        spl_token::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)?;
+
+        acct.autopay_paid_amount = acct.autopay_paid_amount.saturating_add(amount);
+        if amount == goal_amount {
+            acct.autopay_last_cycle = acct.cycle_count;
+        }
+        acct.post_repayment(amount, now);
+        emit!(PaymentPosted {
+            owner: acct.owner,
+            payer: acct.owner,
+            amount,
+            remaining_used_amount: acct.used_amount,
+            ts: now
+        });
+        Ok(())
+    }
+
+    /// Fecha o ciclo corrente: fatura a próxima parcela do cronograma junto com o saldo
+    /// não pago da fatura anterior. O PDA `Statement` é semeado pela abertura da conta
+    /// (`opened_ts`) e pelo índice do ciclo (`cycle_count + 1`): cada fatura existe uma única
+    /// vez, e uma conta encerrada e reaberta começa uma série nova.
+    pub fn statement_close(ctx: Context<StatementClose>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let cfg = &ctx.accounts.config;
+        let acct = &mut ctx.accounts.credit_account;
+        require!(now >= acct.next_statement_ts, CreditError::StatementNotDue);
+        acct.accrue(cfg, now);
+        // O corte cai no vencimento da fatura anterior: a multa dela é apurada antes
+        // de o saldo virar vencido (o `accrue` só a cobra depois do vencimento)
+        let late_fee_amount = acct.assess_late_fee(cfg);
+        if late_fee_amount > 0 {
+            emit!(InterestAccrued {
+                owner: acct.owner,
+                interest_amount: 0,
+                late_fee_amount,
+                days: 0,
+                ts: now
+            });
+        }
+
+        // Desloca o cronograma: a parcela do slot 0 entra nesta fatura
+        let billed = acct.schedule_amount[0];
+        acct.schedule_amount.rotate_left(1);
+        acct.schedule_amount[MAX_INSTALLMENTS - 1] = 0;
+
+        let carried_min = acct.min_payment_due_amount;
+        if carried_min > 0 && acct.past_due_since_ts == 0 {
+            // Mínimo da fatura anterior não pago até o vencimento
+            acct.past_due_since_ts = acct.due_date_ts;
+        }
+        // Saldo não pago da fatura anterior vira o vencido mais recente; os saldos andam
+        // um ciclo e os que passam do histórico se somam ao último
+        let oldest = PAST_DUE_CYCLES - 1;
+        let unpaid_statement_amount = acct.statement_balance_amount;
+        let past_due = &mut acct.past_due_cycles_amount;
+        past_due[oldest] = past_due[oldest].saturating_add(past_due[oldest - 1]);
+        past_due.copy_within(0..oldest - 1, 1);
+        past_due[0] = unpaid_statement_amount;
+        acct.statement_balance_amount = billed;
+        let floor_amount = cfg
+            .settlement_quote(&acct.mint, price_of(&ctx.accounts.settlement_price))?
+            .from_usdc(cfg.min_payment_floor_usdc);
+        let total_due_amount = acct.statement_due_amount();
+        let min_payment_amount = min_payment(cfg, total_due_amount, floor_amount)
+            .saturating_add(carried_min)
+            .min(total_due_amount);
+        let due_date_ts = next_billing_ts(acct.next_statement_ts, acct.billing_cycle_day);
+
+        acct.min_payment_due_amount = min_payment_amount;
+        acct.due_date_ts = due_date_ts;
+        acct.late_fee_assessed = false;
+        acct.autopay_paid_amount = 0;
+        acct.next_statement_ts = due_date_ts;
+        acct.cycle_count = acct.cycle_count.saturating_add(1);
+        acct.update_score(now);
+
+        let st = &mut ctx.accounts.statement;
+        st.owner = acct.owner;
+        st.mint = acct.mint;
+        st.cycle_index = acct.cycle_count;
+        st.total_due_amount = total_due_amount;
+        st.min_payment_amount = min_payment_amount;
+        st.due_date_ts = due_date_ts;
+        st.closed_ts = now;
+        st.bump = ctx.bumps.statement;
+
+        emit!(StatementClosed {
+            owner: acct.owner,
+            cycle_index: acct.cycle_count,
+            total_due_amount,
+            min_payment_amount,
+            due_date_ts
+        });
+        Ok(())
+    }
+
+    /// Crank permissionless: aplica juros de mora diários e multa sobre o saldo vencido.
+    pub fn accrue_interest(ctx: Context<AccountCrank>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts
+            .credit_account
+            .accrue(&ctx.accounts.config, now);
+        Ok(())
+    }
+
+    /// Recalcula o health factor a partir das posições do owner no `collateral_vault`.
+    /// `remaining_accounts`: PDA `PositionIndex` do owner (mesmo que ainda não exista) seguido de
+    /// trincas `[Position, PriceAccount, Mint]`, uma para cada posição aberta do owner.
+    pub fn refresh_health<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RefreshHealth<'info>>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let cfg = &ctx.accounts.config;
+        let acct = &mut ctx.accounts.credit_account;
+        acct.accrue(cfg, now);
+
+        // Colateral avaliado em USDC; a dívida é convertida da moeda da conta
+        let settlement =
+            cfg.settlement_rate(&acct.mint, price_of(&ctx.accounts.settlement_price), now)?;
+        let collateral = value_collateral(&acct.owner, ctx.remaining_accounts, cfg, now)?;
+        let owed_usdc = settlement.to_usdc(acct.total_owed_amount());
+        acct.collateral_value_usdc = collateral.value_usdc;
+        acct.weighted_collateral_usdc = collateral.weighted_usdc;
+        acct.health_factor_bps = health_factor_bps(collateral.weighted_usdc, owed_usdc);
+        acct.last_health_ts = now;
+
+        emit!(HealthRefreshed {
+            owner: acct.owner,
+            collateral_value_usdc: collateral.value_usdc,
+            weighted_collateral_usdc: collateral.weighted_usdc,
+            owed_usdc,
+            health_factor_bps: acct.health_factor_bps,
+            ts: now
+        });
+        Ok(())
+    }
+
+    /// Saque de colateral: o `collateral_vault` só libera com a assinatura do PDA do protocolo.
+    /// Com dívida em aberto, o HF recalculado após o saque precisa ficar em
+    /// `min_hf_bps_for_withdraw`. `remaining_accounts`: mesmo layout de `refresh_health`.
+    pub fn withdraw_collateral<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawCollateral<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let cfg = &ctx.accounts.config;
+        let owner = ctx.accounts.owner.key();
+
+        // Sem conta de crédito (nunca aberta ou encerrada) não há dívida a proteger
+        let credit_info = ctx.accounts.credit_account.to_account_info();
+        if !credit_info.data_is_empty() {
+            let mut acct =
+                CreditAccount::try_deserialize(&mut &credit_info.try_borrow_data()?[..])?;
+            // Conta baixada como perda: o colateral fica com o protocolo
+            require!(
+                acct.status != AccountStatus::Closed as u8,
+                CreditError::ChargedOff
+            );
+            acct.accrue(cfg, now);
+
+            let position = &ctx.accounts.position;
+            let price = &ctx.accounts.price_account;
+            require_keys_eq!(
+                position.mint,
+                price.mint,
+                CreditError::InvalidCollateralAccounts
+            );
+            let settlement_price = price_of(&ctx.accounts.settlement_price);
+            let settlement = cfg.settlement_rate(&acct.mint, settlement_price, now)?;
+            let collateral = value_collateral(&owner, ctx.remaining_accounts, cfg, now)?;
+            let withdrawn_value_usdc = (amount as u128 * price.price_usdc_6 as u128
+                / 10u128.pow(ctx.accounts.mint.decimals as u32))
+            .min(u64::MAX as u128) as u64;
+            let weighted_after = collateral
+                .weighted_usdc
+                .saturating_sub(bps_of(withdrawn_value_usdc, position.ltv_bps));
+            let hf_after =
+                health_factor_bps(weighted_after, settlement.to_usdc(acct.total_owed_amount()));
+            require!(
+                hf_after >= cfg.min_hf_bps_for_withdraw,
+                CreditError::HfTooLow
+            );
+
+            acct.collateral_value_usdc = collateral.value_usdc.saturating_sub(withdrawn_value_usdc);
+            acct.weighted_collateral_usdc = weighted_after;
+            acct.health_factor_bps = hf_after;
+            acct.last_health_ts = now;
+            acct.try_serialize(&mut &mut credit_info.try_borrow_mut_data()?[..])?;
+        }
+
+        let seeds: &[&[u8]] = &[b"credit_vault", &[cfg.vault_authority_bump]];
+        let signer = &[seeds];
+        let cpi_accounts = collateral_vault::cpi::accounts::WithdrawPump {
+            vault_config: ctx.accounts.vault_config.to_account_info(),
+            liquidation_authority: ctx.accounts.vault_authority.to_account_info(),
+            vault: ctx.accounts.collateral_vault.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            position: ctx.accounts.position.to_account_info(),
+            vault_ata: ctx.accounts.vault_ata.to_account_info(),
+            owner_ata: ctx.accounts.owner_ata.to_account_info(),
+            owner: ctx.accounts.owner.to_account_info(),
+            associated_token_program: ctx.accounts.associated_token_program.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.collateral_vault_program.to_account_info(),
+            cpi_accounts,
+            signer,
+        );
+        collateral_vault::cpi::withdraw_pump(cpi_ctx, amount)
+    }
+
+    /// Liquidação permissionless quando o HF recalculado fica abaixo de 1.0: o liquidante
+    /// quita até `close_factor_bps` da dívida e recebe o colateral equivalente + bônus.
+    /// `remaining_accounts`: mesmo layout de `refresh_health`.
+    pub fn liquidate<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Liquidate<'info>>,
+        repay_amount: u64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let cfg = &ctx.accounts.config;
+        let acct = &mut ctx.accounts.credit_account;
+        acct.accrue(cfg, now);
+
+        let settlement =
+            cfg.settlement_rate(&acct.mint, price_of(&ctx.accounts.settlement_price), now)?;
+        let collateral = value_collateral(&acct.owner, ctx.remaining_accounts, cfg, now)?;
+        let owed = acct.total_owed_amount();
+        let hf_before = health_factor_bps(collateral.weighted_usdc, settlement.to_usdc(owed));
+        require!(hf_before < 10_000, CreditError::NotLiquidatable);
+        require!(repay_amount > 0, CreditError::InvalidAmount);
+        require!(
+            repay_amount <= bps_of(owed, cfg.close_factor_bps),
+            CreditError::CloseFactorExceeded
+        );
+
+        // Colateral alvo: mesma checagem da avaliação
+        let position = &ctx.accounts.position;
+        let price = &ctx.accounts.price_account;
+        let collateral_mint = &ctx.accounts.collateral_mint;
+        require_keys_eq!(
+            position.owner,
+            acct.owner,
+            CreditError::InvalidCollateralAccounts
+        );
+        require_keys_eq!(
+            position.mint,
+            price.mint,
+            CreditError::InvalidCollateralAccounts
+        );
+        require!(
+            price.price_usdc_6 > 0,
+            CreditError::InvalidCollateralAccounts
+        );
+        require!(
+            cfg.max_price_age_secs == 0 || now - price.last_ts <= cfg.max_price_age_secs,
+            CreditError::StalePrice
+        );
+
+        let unit = 10u128.pow(collateral_mint.decimals as u32);
+        // Quitação na moeda da conta, colateral cotado em USDC
+        let seize_value_usdc = settlement.to_usdc(repay_amount) as u128
+            * (10_000 + cfg.liquidation_bonus_bps as u128)
+            / 10_000;
+        let seize_amount = (seize_value_usdc * unit / price.price_usdc_6 as u128)
+            .min(position.amount as u128) as u64;
+        let seized_value_usdc = (seize_amount as u128 * price.price_usdc_6 as u128 / unit) as u64;
+
+        // 1) liquidante paga a dívida no cofre do protocolo
+        let cpi_accounts = spl_token::TransferChecked {
+            from: ctx.accounts.liquidator_ata.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.vault.to_account_info(),
+            authority: ctx.accounts.liquidator.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        spl_token::transfer_checked(cpi_ctx, repay_amount, ctx.accounts.mint.decimals)?;
+
+        // 2) cofre de colateral entrega o ativo ao liquidante (assinado pelo PDA do protocolo)
+        let seeds: &[&[u8]] = &[b"credit_vault", &[cfg.vault_authority_bump]];
+        let signer = &[seeds];
+        let cpi_accounts = collateral_vault::cpi::accounts::SeizeCollateral {
+            vault_config: ctx.accounts.vault_config.to_account_info(),
+            liquidation_authority: ctx.accounts.vault_authority.to_account_info(),
+            vault: ctx.accounts.collateral_vault.to_account_info(),
+            mint: ctx.accounts.collateral_mint.to_account_info(),
+            position: ctx.accounts.position.to_account_info(),
+            vault_ata: ctx.accounts.vault_collateral_ata.to_account_info(),
+            recipient_ata: ctx.accounts.liquidator_collateral_ata.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.collateral_vault_program.to_account_info(),
+            cpi_accounts,
+            signer,
+        );
+        collateral_vault::cpi::seize_collateral(cpi_ctx, seize_amount)?;
+
+        acct.apply_payment(repay_amount);
+        let weighted_after = collateral
+            .weighted_usdc
+            .saturating_sub(bps_of(seized_value_usdc, position.ltv_bps));
+        acct.collateral_value_usdc = collateral.value_usdc.saturating_sub(seized_value_usdc);
+        acct.weighted_collateral_usdc = weighted_after;
+        acct.health_factor_bps =
+            health_factor_bps(weighted_after, settlement.to_usdc(acct.total_owed_amount()));
+        acct.last_health_ts = now;
+
+        emit!(Liquidated {
+            owner: acct.owner,
+            liquidator: ctx.accounts.liquidator.key(),
+            repaid_amount: repay_amount,
+            collateral_mint: collateral_mint.key(),
+            seized_amount: seize_amount,
+            seized_value_usdc,
+            hf_before_bps: hf_before,
+            hf_after_bps: acct.health_factor_bps
+        });
+        Ok(())
+    }
+
+    /// Admin ou `risk_authority`. `reason` segue os códigos de `FreezeReason` (exceto `None`).
+    /// Contas em default ou encerradas não são congeladas.
+    pub fn soft_freeze(ctx: Context<Freeze>, reason: u8) -> Result<()> {
+        let reason = FreezeReason::freeze_code(reason)?;
+        let authority = ctx.accounts.authority.key();
+        ctx.accounts
+            .credit_account
+            .freeze(AccountStatus::SoftFrozen, reason, authority)
+    }
+
+    pub fn hard_freeze(ctx: Context<Freeze>, reason: u8) -> Result<()> {
+        let reason = FreezeReason::freeze_code(reason)?;
+        let authority = ctx.accounts.authority.key();
+        ctx.accounts
+            .credit_account
+            .freeze(AccountStatus::HardFrozen, reason, authority)
+    }
+
+    /// Volta ao status anterior ao bloqueio (`Active` ou `Delinquent`).
+    pub fn unfreeze(ctx: Context<Freeze>) -> Result<()> {
+        let authority = ctx.accounts.authority.key();
+        let acct = &mut ctx.accounts.credit_account;
+        require!(
+            acct.status == AccountStatus::SoftFrozen as u8
+                || acct.status == AccountStatus::HardFrozen as u8,
+            CreditError::AccountNotFrozen
+        );
+        let previous = AccountStatus::try_from(acct.pre_freeze_status)?;
+        let reason = if matches!(previous, AccountStatus::Delinquent) {
+            FreezeReason::PastDue
+        } else {
+            FreezeReason::None
+        };
+        acct.set_status(previous, reason as u8, authority);
+        Ok(())
+    }
+
+    /// Crank permissionless da máquina de status por atraso. Passada a carência, a conta
+    /// ativa vira `Delinquent`; `default_after_days` depois, qualquer conta vira `Defaulted`.
+    /// Conta inadimplente com o mínimo em dia volta a `Active`. A carência é
+    /// `grace_volatile_days` se alguma posição é de token pump (volátil), senão `grace_any_days`.
+    /// `remaining_accounts`: PDA `PositionIndex` do owner seguido de pares
+    /// `[Position, PDA PumpToken do mint]` de todas as posições.
+    pub fn update_status<'info>(
+        ctx: Context<'_, '_, 'info, 'info, AccountCrank<'info>>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let cfg = &ctx.accounts.config;
+        let acct = &mut ctx.accounts.credit_account;
+        acct.accrue(cfg, now);
+
+        let grace_days = if has_volatile_collateral(&acct.owner, ctx.remaining_accounts)? {
+            cfg.grace_volatile_days
+        } else {
+            cfg.grace_any_days
+        } as u32;
+        let days_past_due = acct.days_past_due(now);
+        let status = acct.status;
+        let next =
+            if status == AccountStatus::Closed as u8 || status == AccountStatus::Defaulted as u8 {
+                None
+            } else if days_past_due > grace_days + cfg.default_after_days as u32 {
+                Some(AccountStatus::Defaulted)
+            } else if days_past_due > grace_days && status == AccountStatus::Active as u8 {
+                Some(AccountStatus::Delinquent)
+            } else if days_past_due == 0 && status == AccountStatus::Delinquent as u8 {
+                Some(AccountStatus::Active)
+            } else {
+                None
+            };
+        if let Some(next) = next {
+            let reason = if matches!(next, AccountStatus::Active) {
+                FreezeReason::None
+            } else {
+                FreezeReason::PastDue
+            };
+            acct.set_status(next, reason as u8, Pubkey::default());
+        }
+        acct.update_score(now);
+        Ok(())
+    }
+
+    /// Admin oferece renegociação do saldo em atraso: nova taxa mensal e prazo.
+    /// A oferta vale até `expires_ts` e só tem efeito se o owner aceitar.
+    pub fn propose_restructure(
+        ctx: Context<ProposeRestructure>,
+        rate_bps_monthly: u32,
+        installments: u8,
+        expires_ts: i64,
+    ) -> Result<()> {
+        require!(
+            installments > 0 && installments as usize <= MAX_INSTALLMENTS,
+            CreditError::InstallmentsNotAllowed
+        );
+        require!(
+            expires_ts > Clock::get()?.unix_timestamp,
+            CreditError::RestructureExpired
+        );
+        let offer = &mut ctx.accounts.offer;
+        offer.owner = ctx.accounts.owner.key();
+        offer.rate_bps_monthly = rate_bps_monthly;
+        offer.installments = installments;
+        offer.expires_ts = expires_ts;
+        offer.bump = ctx.bumps.offer;
+        emit!(RestructureProposed {
+            owner: offer.owner,
+            rate_bps_monthly,
+            installments,
+            expires_ts
+        });
+        Ok(())
+    }
+
+    pub fn cancel_restructure(_ctx: Context<CancelRestructure>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Owner aceita a renegociação: vencido, fatura vencida, juros de mora e multas viram
+    /// um novo parcelamento (tabela Price) nas próximas faturas. As notas vencidas são
+    /// canceladas e substituídas por notas do novo pedido `order_id`, emitidas em nome do
+    /// protocolo. A conta fica marcada como renegociada para o score.
+    /// Cada nota cancelada é conferida contra o seu pedido e abate só o saldo vencido do
+    /// ciclo em que foi faturada; cada ciclo tem de ser cancelado exatamente. O protocolo
+    /// assume o recebível: o cofre paga o líquido cancelado ao beneficiário de cada nota, que
+    /// só pode ser o lojista ou o pool configurado (nota antecipada por ele).
+    /// `remaining_accounts`: trincas `[OrderRecord, nota, token account do beneficiário]` das
+    /// notas a cancelar (`old_note_indices[i]` = índice da nota no pedido), seguidas dos PDAs
+    /// `[b"note", order_id, index]` das novas parcelas.
+    pub fn accept_restructure<'info>(
+        ctx: Context<'_, '_, 'info, 'info, AcceptRestructure<'info>>,
+        order_id: [u8; 32],
+        old_note_indices: Vec<u8>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let cfg = &ctx.accounts.config;
+        let offer = &ctx.accounts.offer;
+        let acct = &mut ctx.accounts.credit_account;
+        require!(now < offer.expires_ts, CreditError::RestructureExpired);
+        let installments = offer.installments;
+        let old_notes_len = old_note_indices.len() * 3;
+        require!(
+            ctx.remaining_accounts.len() == old_notes_len + installments as usize,
+            CreditError::NoteAccountsMismatch
+        );
+        let (old_notes, new_notes) = ctx.remaining_accounts.split_at(old_notes_len);
+        acct.accrue(cfg, now);
+
+        // Saldo consolidado: tudo o que já venceu
+        let overdue_statement_amount = if now > acct.due_date_ts {
+            std::mem::take(&mut acct.statement_balance_amount)
+        } else {
+            0
+        };
+        let mut past_due_left = std::mem::take(&mut acct.past_due_cycles_amount);
+        let mut statement_left = overdue_statement_amount;
+        let billed_amount = past_due_left
+            .iter()
+            .sum::<u64>()
+            .saturating_add(overdue_statement_amount);
+        let charges_amount = std::mem::take(&mut acct.accrued_interest_amount)
+            .saturating_add(std::mem::take(&mut acct.late_fees_amount));
+        let principal_amount = billed_amount.saturating_add(charges_amount);
+        require!(principal_amount > 0, CreditError::NothingToRestructure);
+
+        let schedule = price_table_schedule(principal_amount, offer.rate_bps_monthly, installments)
+            .ok_or(CreditError::MathOverflow)?;
+        let interest_amount: u64 = schedule.iter().map(|p| p.interest_amount).sum();
+        for (slot, part) in acct.schedule_amount.iter_mut().zip(schedule.iter()) {
+            *slot = slot.saturating_add(part.total_amount());
+        }
+        // Juros e multas entram no principal renegociado, junto com os juros novos
+        acct.used_amount = acct
+            .used_amount
+            .saturating_add(charges_amount)
+            .saturating_add(interest_amount);
+
+        let seeds: &[&[u8]] = &[b"credit_vault", &[cfg.vault_authority_bump]];
+        let signer = &[seeds];
+        let issuer = ctx.accounts.vault_authority.key();
+        // Notas das parcelas vencidas, até cobrir exatamente o principal vencido
+        let mut cancelled_amount = 0u64;
+        let mut buyback_amount = 0u64;
+        for (group, &index) in old_notes.chunks_exact(3).zip(old_note_indices.iter()) {
+            let (order_info, note_info, beneficiary_info) = (&group[0], &group[1], &group[2]);
+            let order = Account::<OrderRecord>::try_from(order_info)?;
+            require!(
+                order.owner == acct.owner
+                    && order.status != OrderStatus::Disputed as u8
+                    && index < order.installments,
+                CreditError::InvalidRestructureNote
+            );
+            // Parcela já faturada: cada nota só abate o que ainda vence no próprio ciclo
+            let cycle = order.first_cycle + index as u32;
+            require!(cycle <= acct.cycle_count, CreditError::InvalidRestructureNote);
+            let cycle_left = if cycle == acct.cycle_count {
+                &mut statement_left
+            } else {
+                &mut past_due_left[acct.past_due_slot(cycle)]
+            };
+            let note = load_order_note(&order.order_id, index, note_info)?;
+            let advanced = advanced_by_pool(cfg, &note);
+            require!(
+                (note.status == receivables::NOTE_STATUS_ISSUED
+                    || note.status == receivables::NOTE_STATUS_PAST_DUE
+                    || advanced)
+                    && (advanced || note.beneficiary == order.merchant)
+                    && note.buyer == acct.owner
+                    && note.due_ts <= now
+                    && !note.disputed,
+                CreditError::InvalidRestructureNote
+            );
+            let cut = note.amount.min(*cycle_left);
+            require!(cut > 0, CreditError::InvalidRestructureNote);
+            *cycle_left -= cut;
+            let net_cut = receivables::net_reduction(note.net_amount, note.amount, cut);
+            let beneficiary = note.beneficiary;
+
+            let cpi_accounts = receivables::cpi::accounts::ReduceNote {
+                issuer: ctx.accounts.vault_authority.to_account_info(),
+                note_state: note_info.clone(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.receivables_program.to_account_info(),
+                cpi_accounts,
+                signer,
+            );
+            receivables::cpi::reduce_note(cpi_ctx, cut)?;
+
+            // O protocolo assume o recebível e recompra do beneficiário (nota renegociada de
+            // novo já é do protocolo)
+            if beneficiary != issuer && net_cut > 0 {
+                let cpi_accounts = spl_token::TransferChecked {
+                    from: ctx.accounts.vault.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: beneficiary_info.clone(),
+                    authority: ctx.accounts.vault_authority.to_account_info(),
+                };
+                let cpi_ctx = CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    cpi_accounts,
+                    signer,
+                );
+                buy_back_note(
+                    cpi_ctx,
+                    &beneficiary,
+                    beneficiary_info,
+                    &ctx.accounts.mint,
+                    net_cut,
+                )?;
+                buyback_amount += net_cut;
+            }
+            cancelled_amount += cut;
+        }
+        require!(
+            statement_left == 0 && past_due_left.iter().all(|&left| left == 0),
+            CreditError::InvalidRestructureNote
+        );
+
+        let mut due_ts = acct.next_statement_ts;
+        for (i, note) in new_notes.iter().enumerate() {
+            due_ts = next_billing_ts(due_ts, acct.billing_cycle_day);
+            let cpi_accounts = receivables::cpi::accounts::MintNote {
+                issuer: ctx.accounts.vault_authority.to_account_info(),
+                payer: ctx.accounts.owner.to_account_info(),
+                note_state: note.clone(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.receivables_program.to_account_info(),
+                cpi_accounts,
+                signer,
+            );
+            receivables::cpi::mint_note(
+                cpi_ctx,
+                order_id,
+                i as u8,
+                receivables::NoteParams {
+                    buyer: acct.owner,
+                    merchant: issuer,
+                    amount: schedule[i].total_amount(),
+                    due_ts,
+                    net_amount: schedule[i].principal_amount,
+                    plan: InstallmentPlan::Restructure as u8,
+                    mint: acct.mint,
+                },
+            )?;
+            emit!(InstallmentScheduled {
+                order_id,
+                index: i as u8,
+                principal_amount: schedule[i].principal_amount,
+                interest_amount: schedule[i].interest_amount,
+                due_ts
+            });
+        }
+
+        let order = &mut ctx.accounts.order_record;
+        order.order_id = order_id;
+        order.owner = acct.owner;
+        order.merchant = issuer;
+        order.mint = acct.mint;
+        order.authority = acct.owner;
+        order.amount = principal_amount;
+        order.interest_amount = interest_amount;
+        order.merchant_net_amount = principal_amount;
+        order.installments = installments;
+        order.plan = InstallmentPlan::Restructure as u8;
+        order.first_cycle = acct.cycle_count + 1;
+        order.created_ts = now;
+        order.status = OrderStatus::Open as u8;
+        order.refunded_amount = 0;
+        order.disputed_ts = 0;
+        order.dispute_deadline_ts = 0;
+        order.disputed_notes_amount = [0; MAX_INSTALLMENTS];
+        order.merchant_debt_amount = 0;
+        order.bump = ctx.bumps.order_record;
+
+        // Atraso renegociado: o mínimo e o bloqueio por atraso deixam de valer
+        acct.min_payment_due_amount =
+            acct.min_payment_due_amount.min(acct.statement_balance_amount);
+        acct.past_due_since_ts = 0;
+        acct.restructured_ts = now;
+        acct.restructure_count = acct.restructure_count.saturating_add(1);
+        acct.cure_delinquency();
+        acct.update_score(now);
+
+        emit!(AccountRestructured {
+            owner: acct.owner,
+            order_id,
+            principal_amount,
+            interest_amount,
+            installments,
+            cancelled_notes_amount: cancelled_amount,
+            buyback_amount,
+            ts: now
+        });
+        Ok(())
+    }
+
+    /// Encerra a conta e devolve o rent ao owner. Exige saldo zerado, nenhuma disputa,
+    /// nenhum cartão adicional e nenhum colateral depositado; crédito de estornos
+    /// restante é pago ao ATA do owner e os controles de gasto também são fechados.
+    /// Contas em default ou já encerradas ficam.
+    /// `remaining_accounts`: PDA `PositionIndex` do owner seguido de cada `Position`.
+    pub fn close_account<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CloseAccount<'info>>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let cfg = &ctx.accounts.config;
+        let acct = &mut ctx.accounts.credit_account;
+        acct.accrue(cfg, now);
+        require!(
+            acct.status != AccountStatus::Defaulted as u8
+                && acct.status != AccountStatus::Closed as u8,
+            CreditError::AccountNotClosable
+        );
+        require!(
+            acct.total_owed_amount() == 0 && acct.open_disputes == 0,
+            CreditError::OutstandingBalance
+        );
+        // Principal de saques já pago precisa voltar ao pool antes (`return_cash_to_pool`)
+        require!(acct.cash_repaid_amount == 0, CreditError::CashNotReturned);
+        require!(acct.delegate_count == 0, CreditError::DelegatesActive);
+        require!(
+            !acct.has_spend_controls || ctx.accounts.spend_controls.is_some(),
+            CreditError::SpendControlsMissing
+        );
+        require!(
+            locked_collateral(&acct.owner, ctx.remaining_accounts)? == 0,
+            CreditError::CollateralLocked
+        );
+
+        let refund_amount = std::mem::take(&mut acct.credit_balance_amount);
+        if refund_amount > 0 {
+            let seeds: &[&[u8]] = &[b"credit_vault", &[cfg.vault_authority_bump]];
+            let signer = &[seeds];
+            let cpi_accounts = spl_token::TransferChecked {
+                from: ctx.accounts.vault.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                to: ctx.accounts.owner_ata.to_account_info(),
+                authority: ctx.accounts.vault_authority.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+                signer,
+            );
+            spl_token::transfer_checked(cpi_ctx, refund_amount, ctx.accounts.mint.decimals)?;
+        }
+
+        emit!(AccountClosed {
+            owner: acct.owner,
+            authority: acct.owner,
+            refund_amount,
+            charged_off_amount: 0,
+            ts: now
+        });
+        Ok(())
+    }
+
+    /// Admin baixa como perda uma conta em default: zera os saldos e a marca como `Closed`.
+    /// O PDA é mantido para que o owner não reabra a conta com histórico limpo, e o colateral
+    /// depositado fica bloqueado (`withdraw_collateral` recusa contas encerradas assim).
+    /// As notas ainda não pagas viram `defaulted` no receivables e o principal de saques em
+    /// aberto é baixado no pool (`advance_pool::write_off_disbursed`).
+    /// `remaining_accounts`: pares `[OrderRecord, nota]`; `note_indices[i]` = índice da nota do par i.
+    pub fn force_close_account<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ForceCloseAccount<'info>>,
+        note_indices: Vec<u8>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let acct = &mut ctx.accounts.credit_account;
+        require!(
+            acct.status == AccountStatus::Defaulted as u8,
+            CreditError::AccountNotDefaulted
+        );
+        let pairs = ctx.remaining_accounts.chunks_exact(2);
+        require!(
+            pairs.remainder().is_empty() && pairs.len() == note_indices.len(),
+            CreditError::NoteAccountsMismatch
+        );
+        let seeds: &[&[u8]] = &[b"credit_vault", &[ctx.accounts.config.vault_authority_bump]];
+        let signer = &[seeds];
+        for (pair, &index) in pairs.zip(note_indices.iter()) {
+            let order = Account::<OrderRecord>::try_from(&pair[0])?;
+            require_keys_eq!(order.owner, acct.owner, CreditError::NoteAccountsMismatch);
+            require!(index < order.installments, CreditError::NoteAccountsMismatch);
+            let note = load_order_note(&order.order_id, index, &pair[1])?;
+            // Parcela já quitada (ciclo sem saldo) ou nota cancelada: nada a baixar
+            let cycle = order.first_cycle + index as u32;
+            if note.status == receivables::NOTE_STATUS_CANCELLED
+                || *acct.installment_bucket(cycle) == 0
+            {
+                continue;
+            }
+            let cpi_accounts = receivables::cpi::accounts::MarkDefaulted {
+                issuer: ctx.accounts.vault_authority.to_account_info(),
+                note_state: pair[1].clone(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.receivables_program.to_account_info(),
+                cpi_accounts,
+                signer,
+            );
+            receivables::cpi::mark_defaulted(cpi_ctx)?;
+        }
+
+        let cash_written_off = std::mem::take(&mut acct.cash_balance_amount);
+        if cash_written_off > 0 {
+            let cpi_accounts = advance_pool::cpi::accounts::WriteOffDisbursed {
+                credit_authority: ctx.accounts.vault_authority.to_account_info(),
+                pool: ctx.accounts.pool.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.advance_pool_program.to_account_info(),
+                cpi_accounts,
+                signer,
+            );
+            advance_pool::cpi::write_off_disbursed(cpi_ctx, cash_written_off)?;
+        }
+
+        let charged_off_amount = acct.total_owed_amount();
+        acct.used_amount = 0;
+        acct.past_due_cycles_amount = [0; PAST_DUE_CYCLES];
+        acct.statement_balance_amount = 0;
+        acct.schedule_amount = [0; MAX_INSTALLMENTS];
+        acct.accrued_interest_amount = 0;
+        acct.late_fees_amount = 0;
+        acct.cash_limit_amount = 0;
+        acct.disputed_amount = 0;
+        acct.min_payment_due_amount = 0;
+        acct.limit_amount = 0;
+        acct.auto_limit = false;
+        acct.autopay_mode = AutopayMode::Off as u8;
+        acct.set_status(
+            AccountStatus::Closed,
+            FreezeReason::PastDue as u8,
+            ctx.accounts.admin.key(),
+        );
+        emit!(AccountClosed {
+            owner: acct.owner,
+            authority: ctx.accounts.admin.key(),
+            refund_amount: 0,
+            charged_off_amount,
+            ts: now
+        });
+        Ok(())
+    }
+}
+
+// ---------------- Accounts ----------------
+#[account]
+pub struct CreditAccount {
+    pub owner: Pubkey,
+    pub mint: Pubkey, // moeda de liquidação da conta; valores `_amount` nas unidades dela
+    pub limit_amount: u64,
+    pub used_amount: u64,
+    pub health_factor_bps: u32,
+    pub score: u16,
+    pub billing_cycle_day: u8,
+    pub status: u8,
+    pub total_repaid_amount: u64,
+    pub last_payment_ts: i64,
+    pub cycle_count: u32,
+    pub next_statement_ts: i64,
+    /// Principal não pago de faturas anteriores, por ciclo: `[0]` = fatura anterior à
+    /// corrente, `[k]` = k ciclos antes dela; o último acumula as mais antigas
+    pub past_due_cycles_amount: [u64; PAST_DUE_CYCLES],
+    pub statement_balance_amount: u64, // principal em aberto da última fatura fechada
+    pub min_payment_due_amount: u64,
+    pub due_date_ts: i64,
+    pub schedule_amount: [u64; MAX_INSTALLMENTS], // parcelas a faturar; [0] = próximo ciclo
+    pub accrued_interest_amount: u64,             // juros de mora, fora do principal
+    pub late_fees_amount: u64,
+    pub last_accrual_ts: i64,
+    pub late_fee_assessed: bool, // multa já cobrada sobre a fatura corrente
+    pub collateral_value_usdc: u64, // última avaliação do colateral (refresh_health)
+    pub weighted_collateral_usdc: u64, // idem, ponderado pelo LTV de cada posição
+    pub last_health_ts: i64,
+    pub freeze_reason: u8,     // FreezeReason do último bloqueio
+    pub pre_freeze_status: u8, // status antes do bloqueio; restaurado no `unfreeze`
+    pub auto_limit: bool,
+    pub limit_ceiling_amount: u64, // teto do limite automático, definido pelo admin
+    pub opened_ts: i64,
+    pub on_time_payments: u16, // faturas cujo mínimo foi pago até o vencimento
+    pub late_payments: u16,
+    pub past_due_since_ts: i64, // vencimento do mínimo mais antigo em aberto (0 = em dia)
+    pub credit_balance_amount: u64, // crédito de estornos de parcelas já pagas
+    pub disputed_amount: u64,     // parcelas em disputa, fora da cascata e do mínimo
+    pub open_disputes: u8,
+    pub has_spend_controls: bool, // exige o PDA `SpendControls` no charge
+    pub autopay_mode: u8,         // AutopayMode
+    pub autopay_cap_amount: u64,    // teto do débito automático por fatura (0 = sem teto)
+    pub autopay_last_cycle: u32,  // última fatura em que o débito automático atingiu o alvo
+    pub autopay_paid_amount: u64,   // já debitado automaticamente na fatura corrente
+    pub restructured_ts: i64,     // última renegociação (0 = nunca)
+    pub restructure_count: u8,
+    pub delegate_count: u8,     // cartões adicionais ativos
+    pub cash_limit_amount: u64,   // sublimite de saque em dinheiro (dentro do limite total)
+    pub cash_balance_amount: u64, // principal de saques em aberto; também em `used_amount`
+    pub cash_last_accrual_ts: i64,
+    pub cash_repaid_amount: u64, // principal de saque pago, a devolver ao pool
+    pub bump: u8,
+}
+
+impl CreditAccount {
+    /// Dias corridos desde o vencimento do pagamento mínimo mais antigo ainda em aberto.
+    pub fn days_past_due(&self, now: i64) -> u32 {
+        let since = if self.past_due_since_ts != 0 {
+            self.past_due_since_ts
+        } else if self.min_payment_due_amount > 0 && self.due_date_ts != 0 {
+            self.due_date_ts
+        } else {
+            return 0;
+        };
+        ((now - since).max(0) / SECONDS_PER_DAY) as u32
+    }
+
+    /// Score 0..=1000: histórico de pagamentos (400), atraso atual (250), utilização (200)
+    /// e idade da conta (150), menos 150 por um ano após uma renegociação.
+    /// Emite `ScoreBandChanged` quando a faixa muda.
+    pub fn update_score(&mut self, now: i64) {
+        let paid = self.on_time_payments as u64 + self.late_payments as u64;
+        // Sem histórico ainda: metade da pontuação
+        let history = (400 * self.on_time_payments as u64)
+            .checked_div(paid)
+            .unwrap_or(200);
+        let delinquency = 250u64.saturating_sub(self.days_past_due(now) as u64 * 5);
+        let utilization_bps = if self.limit_amount == 0 {
+            if self.used_amount == 0 {
+                0
+            } else {
+                10_000
+            }
+        } else {
+            (self.used_amount as u128 * 10_000 / self.limit_amount as u128).min(10_000) as u64
+        };
+        let utilization = 200 * (10_000 - utilization_bps) / 10_000;
+        let age_days = ((now - self.opened_ts).max(0) / SECONDS_PER_DAY) as u64;
+        let age = (age_days * 150 / 730).min(150);
+        let restructured =
+            self.restructured_ts != 0 && now - self.restructured_ts < 365 * SECONDS_PER_DAY;
+        let penalty = if restructured { 150 } else { 0 };
+
+        let old_band = score_band(self.score);
+        self.score = (history + delinquency + utilization + age).saturating_sub(penalty) as u16;
+        let new_band = score_band(self.score);
+        if new_band != old_band {
+            emit!(ScoreBandChanged {
+                owner: self.owner,
+                old_band,
+                new_band,
+                score: self.score
+            });
+        }
+    }
+
+    /// Transição de status com motivo; `authority` = Pubkey::default() quando automática.
+    pub fn set_status(&mut self, status: AccountStatus, reason: u8, authority: Pubkey) {
+        let old_status = self.status;
+        self.status = status as u8;
+        self.freeze_reason = reason;
+        emit!(AccountStatusChanged {
+            owner: self.owner,
+            old_status,
+            new_status: self.status,
+            reason,
+            authority
+        });
+    }
+
+    /// Congela a conta guardando o status de antes; de soft para hard mantém o original.
+    pub fn freeze(&mut self, status: AccountStatus, reason: u8, authority: Pubkey) -> Result<()> {
+        require!(
+            self.status != AccountStatus::Defaulted as u8
+                && self.status != AccountStatus::Closed as u8,
+            CreditError::AccountNotFreezable
+        );
+        if self.status != AccountStatus::SoftFrozen as u8
+            && self.status != AccountStatus::HardFrozen as u8
+        {
+            self.pre_freeze_status = self.status;
+        }
+        self.set_status(status, reason, authority);
+        Ok(())
+    }
+
+    /// Principal + juros + multas em aberto.
+    pub fn total_owed_amount(&self) -> u64 {
+        self.used_amount
+            .saturating_add(self.accrued_interest_amount)
+            .saturating_add(self.late_fees_amount)
+    }
+
+    /// Lança um pagamento já recebido no cofre: cascata, histórico de pontualidade, score
+    /// e desbloqueio automático de atraso quando o mínimo é quitado.
+    pub fn post_repayment(&mut self, amount: u64, now: i64) {
+        let min_was_due = self.min_payment_due_amount > 0;
+        self.apply_payment(amount);
+        self.total_repaid_amount = self.total_repaid_amount.saturating_add(amount);
+        self.last_payment_ts = now;
+        if min_was_due && self.min_payment_due_amount == 0 {
+            // Mínimo quitado: conta como pontual se antes do vencimento
+            if now <= self.due_date_ts && self.past_due_since_ts == 0 {
+                self.on_time_payments = self.on_time_payments.saturating_add(1);
+            } else {
+                self.late_payments = self.late_payments.saturating_add(1);
+            }
+            self.past_due_since_ts = 0;
+        }
+        self.update_score(now);
+        // Inadimplência cai sozinha quando o mínimo é quitado (default não)
+        if self.min_payment_due_amount == 0 {
+            self.cure_delinquency();
+        }
+    }
+
+    /// `Delinquent` -> `Active`.
+    pub fn cure_delinquency(&mut self) {
+        if self.status == AccountStatus::Delinquent as u8 {
+            self.set_status(
+                AccountStatus::Active,
+                FreezeReason::None as u8,
+                Pubkey::default(),
+            );
+        }
+    }
+
+    /// Quanto pode ser pago agora: o devido menos o que está em disputa.
+    pub fn payable_amount(&self) -> u64 {
+        self.total_owed_amount().saturating_sub(self.disputed_amount)
+    }
+
+    /// Cascata de pagamento: multas, juros, parcelas vencidas, fatura corrente, saque em
+    /// dinheiro e, por fim, parcelas futuras (da mais próxima para a mais distante). Emite `PaymentAllocated`.
+    /// Entre as parcelas vencidas, a fatura mais antiga é quitada primeiro.
+    pub fn apply_payment(&mut self, amount: u64) -> PaymentAllocation {
+        fn take(left: &mut u64, bucket: &mut u64) -> u64 {
+            let part = (*left).min(*bucket);
+            *bucket -= part;
+            *left -= part;
+            part
+        }
+        let mut left = amount;
+        let to_late_fees_amount = take(&mut left, &mut self.late_fees_amount);
+        let to_interest_amount = take(&mut left, &mut self.accrued_interest_amount);
+        let mut to_past_due_amount = 0;
+        for bucket in self.past_due_cycles_amount.iter_mut().rev() {
+            to_past_due_amount += take(&mut left, bucket);
+        }
+        let to_statement_amount = take(&mut left, &mut self.statement_balance_amount);
+        let to_cash_amount = take(&mut left, &mut self.cash_balance_amount);
+        self.cash_repaid_amount = self.cash_repaid_amount.saturating_add(to_cash_amount);
+        let mut to_future_amount = 0;
+        for slot in self.schedule_amount.iter_mut() {
+            to_future_amount += take(&mut left, slot);
+        }
+        self.used_amount = self
+            .used_amount
+            .saturating_sub(
+                to_cash_amount + to_past_due_amount + to_statement_amount + to_future_amount,
+            );
+        self.min_payment_due_amount = self.min_payment_due_amount.saturating_sub(amount);
+
+        let allocation = PaymentAllocation {
+            to_late_fees_amount,
+            to_interest_amount,
+            to_past_due_amount,
+            to_statement_amount,
+            to_cash_amount,
+            to_future_amount,
+        };
+        emit!(PaymentAllocated {
+            owner: self.owner,
+            amount,
+            allocation: allocation.clone()
+        });
+        allocation
+    }
+
+    /// Estorna `amount` da parcela faturada no ciclo `cycle`: abate o saldo onde ela está
+    /// (cronograma, fatura corrente ou vencido) e libera o limite. Retorna a parte que o
+    /// comprador já havia pago.
+    pub fn reverse_installment(&mut self, cycle: u32, amount: u64) -> u64 {
+        let bucket = self.installment_bucket(cycle);
+        let part = amount.min(*bucket);
+        *bucket -= part;
+        self.used_amount = self.used_amount.saturating_sub(part);
+        self.cap_min_payment();
+        amount - part
+    }
+
+    /// Disputa: tira até `amount` da parcela do ciclo `cycle` dos saldos exigíveis e
+    /// guarda em `disputed_amount` (continua consumindo limite). Retorna quanto foi retido.
+    pub fn hold_installment(
+        &mut self,
+        cfg: &Config,
+        floor_amount: u64,
+        cycle: u32,
+        amount: u64,
+    ) -> u64 {
+        let due_before_amount = self.statement_due_amount();
+        let bucket = self.installment_bucket(cycle);
+        let held = amount.min(*bucket);
+        *bucket -= held;
+        self.disputed_amount = self.disputed_amount.saturating_add(held);
+        self.rebase_min_payment(cfg, floor_amount, due_before_amount);
+        held
+    }
+
+    /// Lojista venceu a disputa: o valor retido volta para o saldo do ciclo da parcela.
+    pub fn release_installment(
+        &mut self,
+        cfg: &Config,
+        floor_amount: u64,
+        cycle: u32,
+        held_amount: u64,
+    ) {
+        let due_before_amount = self.statement_due_amount();
+        let bucket = self.installment_bucket(cycle);
+        *bucket = bucket.saturating_add(held_amount);
+        self.disputed_amount = self.disputed_amount.saturating_sub(held_amount);
+        self.rebase_min_payment(cfg, floor_amount, due_before_amount);
+    }
+
+    /// Recalcula o mínimo quando o exigível muda de `due_before_amount` para o atual: aplica a
+    /// diferença da fórmula do mínimo entre os dois saldos, preservando o que já foi pago e o
+    /// mínimo herdado de faturas anteriores.
+    fn rebase_min_payment(&mut self, cfg: &Config, floor_amount: u64, due_before_amount: u64) {
+        let due_amount = self.statement_due_amount();
+        let min_before = min_payment(cfg, due_before_amount, floor_amount);
+        let min_after = min_payment(cfg, due_amount, floor_amount);
+        let min_due = if min_after <= min_before {
+            self.min_payment_due_amount
+                .saturating_sub(min_before - min_after)
+        } else {
+            self.min_payment_due_amount
+                .saturating_add(min_after - min_before)
+        };
+        self.min_payment_due_amount = min_due.min(due_amount);
+    }
+
+    /// Comprador venceu a disputa: o valor retido deixa de ser devido e libera o limite.
+    pub fn drop_held_installment(&mut self, held_amount: u64) {
+        self.disputed_amount = self.disputed_amount.saturating_sub(held_amount);
+        self.used_amount = self.used_amount.saturating_sub(held_amount);
+    }
+
+    /// Saldo onde está a parcela faturada no ciclo `cycle`: cronograma (futuro),
+    /// fatura corrente ou vencido.
+    fn installment_bucket(&mut self, cycle: u32) -> &mut u64 {
+        if cycle > self.cycle_count {
+            let slot = ((cycle - self.cycle_count - 1) as usize).min(MAX_INSTALLMENTS - 1);
+            &mut self.schedule_amount[slot]
+        } else if cycle == self.cycle_count {
+            &mut self.statement_balance_amount
+        } else {
+            let slot = self.past_due_slot(cycle);
+            &mut self.past_due_cycles_amount[slot]
+        }
+    }
+
+    /// Posição em `past_due_cycles_amount` da parcela faturada no ciclo `cycle` (já vencido).
+    pub fn past_due_slot(&self, cycle: u32) -> usize {
+        ((self.cycle_count - cycle - 1) as usize).min(PAST_DUE_CYCLES - 1)
+    }
+
+    /// Total vencido de faturas anteriores.
+    pub fn past_due_amount(&self) -> u64 {
+        self.past_due_cycles_amount.iter().sum()
+    }
+
+    /// O mínimo não pode passar do que continua exigível.
+    fn cap_min_payment(&mut self) {
+        self.min_payment_due_amount = self.min_payment_due_amount.min(self.statement_due_amount());
+    }
+
+    /// Exigível na fatura: vencido, fatura corrente, saque em dinheiro, juros e multas.
+    pub fn statement_due_amount(&self) -> u64 {
+        self.past_due_amount()
+            .saturating_add(self.statement_balance_amount)
+            .saturating_add(self.cash_balance_amount)
+            .saturating_add(self.accrued_interest_amount)
+            .saturating_add(self.late_fees_amount)
+    }
+
+    /// Usa o crédito de estornos para quitar saldos em aberto, na ordem da cascata.
+    pub fn apply_credit_balance(&mut self) {
+        let credit_amount = self.credit_balance_amount.min(self.payable_amount());
+        if credit_amount > 0 {
+            self.apply_payment(credit_amount);
+            self.credit_balance_amount -= credit_amount;
+        }
+    }
+
+    /// Juros de mora (`penalty_rate_bps_daily` por dia cheio) e multa única (`late_fee_bps`)
+    /// sobre o principal vencido e não pago (parcelas em atraso + fatura após o vencimento).
+    /// Emite `InterestAccrued` quando há lançamento.
+    pub fn accrue(&mut self, cfg: &Config, now: i64) {
+        self.accrue_cash(cfg, now);
+        let statement_overdue = self.due_date_ts != 0 && now > self.due_date_ts;
+        let overdue_statement = if statement_overdue {
+            self.statement_balance_amount
+        } else {
+            0
+        };
+        let overdue = self.past_due_amount().saturating_add(overdue_statement);
+        if overdue == 0 {
+            self.last_accrual_ts = now;
+            return;
+        }
+
+        let late_fee_amount = if statement_overdue {
+            self.assess_late_fee(cfg)
+        } else {
+            0
+        };
+
+        // Só dias cheios contam; a fração restante fica para a próxima apuração.
+        // Sem parcelas em atraso, a mora da fatura começa no vencimento.
+        let start = if self.past_due_amount() > 0 {
+            self.last_accrual_ts
+        } else {
+            self.last_accrual_ts.max(self.due_date_ts)
+        };
+        let days = (now - start) / SECONDS_PER_DAY;
+        let interest_amount =
+            (overdue as u128 * cfg.penalty_rate_bps_daily as u128 * days as u128 / 10_000) as u64;
+        self.accrued_interest_amount = self.accrued_interest_amount.saturating_add(interest_amount);
+        self.last_accrual_ts = start + days * SECONDS_PER_DAY;
+
+        if interest_amount > 0 || late_fee_amount > 0 {
+            emit!(InterestAccrued {
+                owner: self.owner,
+                interest_amount,
+                late_fee_amount,
+                days: days as u32,
+                ts: now
+            });
+        }
+    }
+
+    /// HF com `extra_amount` (na moeda da conta) somado à dívida, sobre o colateral
+    /// ponderado da última avaliação.
+    pub fn projected_health_factor_bps(
+        &self,
+        settlement: &SettlementQuote,
+        extra_amount: u64,
+    ) -> u32 {
+        let owed = self.total_owed_amount().saturating_add(extra_amount);
+        health_factor_bps(self.weighted_collateral_usdc, settlement.to_usdc(owed))
+    }
+
+    /// Multa única sobre a fatura corrente, só se o mínimo não foi pago até o vencimento.
+    /// Quem chama garante que o vencimento já chegou.
+    pub fn assess_late_fee(&mut self, cfg: &Config) -> u64 {
+        if self.late_fee_assessed || self.min_payment_due_amount == 0 {
+            return 0;
+        }
+        let late_fee_amount = bps_of(self.statement_balance_amount, cfg.late_fee_bps);
+        self.late_fees_amount = self.late_fees_amount.saturating_add(late_fee_amount);
+        self.late_fee_assessed = true;
+        late_fee_amount
+    }
+
+    /// Juros do saque em dinheiro: `cash_rate_bps_daily` por dia desde o saque, sem
+    /// carência; dia iniciado conta inteiro (cobrado adiantado). Entram em
+    /// `accrued_interest_amount`. Emite `CashInterestAccrued`.
+    fn accrue_cash(&mut self, cfg: &Config, now: i64) {
+        if self.cash_balance_amount == 0 {
+            self.cash_last_accrual_ts = now;
+            return;
+        }
+        let elapsed = now - self.cash_last_accrual_ts;
+        if elapsed <= 0 {
+            return;
+        }
+        let days = (elapsed + SECONDS_PER_DAY - 1) / SECONDS_PER_DAY;
+        let interest_amount =
+            (self.cash_balance_amount as u128 * cfg.cash_rate_bps_daily as u128 * days as u128
+                / 10_000) as u64;
+        self.accrued_interest_amount = self.accrued_interest_amount.saturating_add(interest_amount);
+        self.cash_last_accrual_ts += days * SECONDS_PER_DAY;
+        if interest_amount > 0 {
+            emit!(CashInterestAccrued {
+                owner: self.owner,
+                interest_amount,
+                days: days as u32,
+                ts: now
+            });
+        }
+    }
+}
+
+/// Cartão adicional: outra chave compra pela conta com limite, teto por compra,
+/// validade e lojistas permitidos próprios.
+#[account]
+pub struct Delegate {
+    pub credit_account: Pubkey,
+    pub key: Pubkey,
+    pub spend_limit_amount: u64, // total que pode gastar
+    pub spent_amount: u64,
+    pub per_tx_max_amount: u64, // 0 = sem teto por compra
+    pub expires_ts: i64,      // 0 = não expira
+    pub allowed_merchants: [Pubkey; MAX_DELEGATE_MERCHANTS],
+    pub merchant_count: u8, // 0 = qualquer lojista
+    pub bump: u8,
+}
+
+impl Delegate {
+    pub fn apply(&mut self, params: &DelegateParams) -> Result<()> {
+        require!(
+            params.allowed_merchants.len() <= MAX_DELEGATE_MERCHANTS,
+            CreditError::TooManyMerchants
+        );
+        self.spend_limit_amount = params.spend_limit_amount;
+        self.per_tx_max_amount = params.per_tx_max_amount;
+        self.expires_ts = params.expires_ts;
+        self.allowed_merchants = [Pubkey::default(); MAX_DELEGATE_MERCHANTS];
+        self.allowed_merchants[..params.allowed_merchants.len()]
+            .copy_from_slice(&params.allowed_merchants);
+        self.merchant_count = params.allowed_merchants.len() as u8;
+        Ok(())
+    }
+
+    /// Confere validade, lojista e limites da compra e soma ao gasto do cartão.
+    pub fn authorize(&mut self, amount: u64, merchant: &Pubkey, now: i64) -> Result<()> {
+        require!(
+            self.expires_ts == 0 || now < self.expires_ts,
+            CreditError::DelegateExpired
+        );
+        require!(
+            self.merchant_count == 0
+                || self.allowed_merchants[..self.merchant_count as usize].contains(merchant),
+            CreditError::MerchantNotAllowed
+        );
+        require!(
+            self.per_tx_max_amount == 0 || amount <= self.per_tx_max_amount,
+            CreditError::DelegateTxCapExceeded
+        );
+        let spent_amount = self
+            .spent_amount
+            .checked_add(amount)
+            .ok_or(CreditError::MathOverflow)?;
+        require!(
+            spent_amount <= self.spend_limit_amount,
+            CreditError::DelegateLimitExceeded
+        );
+        self.spent_amount = spent_amount;
+        Ok(())
+    }
+}
+
+/// Controles antifraude do owner (PDA `[b"controls", owner]`). Janelas deslizantes em baldes:
+/// 24h em baldes de 1h e 7 dias em baldes de 1 dia; cada compra cai no balde do momento
+/// e os baldes mais antigos que a janela são zerados ao avançar.
+#[account]
+pub struct SpendControls {
+    pub owner: Pubkey,
+    pub daily_limit_amount: u64, // 0 = sem limite
+    pub weekly_limit_amount: u64,
+    pub max_tx_per_day: u16,
+    pub per_tx_max_amount: u64,
+    pub blocked_categories: u32, // bit i = categoria de lojista i bloqueada
+    pub hour_spent_amount: [u64; HOURS_PER_DAY],
+    pub hour_tx_count: [u16; HOURS_PER_DAY],
+    pub last_hour: i64, // hora (ts / 3600) do balde mais recente
+    pub day_spent_amount: [u64; DAYS_PER_WEEK],
+    pub last_day: i64, // dia (ts / 86400) do balde mais recente
+    pub bump: u8,
+}
+
+impl SpendControls {
+    /// Confere a compra contra cada controle e soma aos baldes correntes das janelas.
+    /// `category`: categoria cadastrada do lojista; só é exigida se há categorias bloqueadas.
+    pub fn authorize(&mut self, amount: u64, category: Option<u8>, now: i64) -> Result<()> {
+        if self.blocked_categories != 0 {
+            let category = category.ok_or(CreditError::MerchantNotRegistered)?;
+            require!(
+                category < 32 && self.blocked_categories & (1 << category) == 0,
+                CreditError::CategoryBlocked
+            );
+        }
+        require!(
+            self.per_tx_max_amount == 0 || amount <= self.per_tx_max_amount,
+            CreditError::PerTxCapExceeded
+        );
+        self.advance_windows(now);
+        let day_tx_count = self
+            .hour_tx_count
+            .iter()
+            .fold(0u16, |acc, n| acc.saturating_add(*n));
+        require!(
+            self.max_tx_per_day == 0 || day_tx_count < self.max_tx_per_day,
+            CreditError::DailyTxCountExceeded
+        );
+        let day_spent_amount = self
+            .hour_spent_amount
+            .iter()
+            .fold(amount, |acc, v| acc.saturating_add(*v));
+        require!(
+            self.daily_limit_amount == 0 || day_spent_amount <= self.daily_limit_amount,
+            CreditError::DailyLimitExceeded
+        );
+        let week_spent_amount = self
+            .day_spent_amount
+            .iter()
+            .fold(amount, |acc, v| acc.saturating_add(*v));
+        require!(
+            self.weekly_limit_amount == 0 || week_spent_amount <= self.weekly_limit_amount,
+            CreditError::WeeklyLimitExceeded
+        );
+        let hour = (self.last_hour % HOURS_PER_DAY as i64) as usize;
+        let day = (self.last_day % DAYS_PER_WEEK as i64) as usize;
+        self.hour_spent_amount[hour] = self.hour_spent_amount[hour].saturating_add(amount);
+        self.hour_tx_count[hour] = self.hour_tx_count[hour].saturating_add(1);
+        self.day_spent_amount[day] = self.day_spent_amount[day].saturating_add(amount);
+        Ok(())
+    }
+
+    /// Avança os baldes até `now`, zerando os que saíram da janela.
+    fn advance_windows(&mut self, now: i64) {
+        let hour = now / 3_600;
+        let elapsed = (hour - self.last_hour).clamp(0, HOURS_PER_DAY as i64);
+        for k in 1..=elapsed {
+            let i = ((self.last_hour + k) % HOURS_PER_DAY as i64) as usize;
+            self.hour_spent_amount[i] = 0;
+            self.hour_tx_count[i] = 0;
+        }
+        self.last_hour = self.last_hour.max(hour);
+
+        let day = now / SECONDS_PER_DAY;
+        let elapsed = (day - self.last_day).clamp(0, DAYS_PER_WEEK as i64);
+        for k in 1..=elapsed {
+            self.day_spent_amount[((self.last_day + k) % DAYS_PER_WEEK as i64) as usize] = 0;
+        }
+        self.last_day = self.last_day.max(day);
+    }
+}
+
+/// Cadastro do lojista feito pelo admin (PDA `[b"merchant", merchant]`).
+#[account]
+pub struct MerchantProfile {
+    pub merchant: Pubkey,
+    pub category: u8, // bit de `SpendControls::blocked_categories`
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct SpendControlsParams {
+    pub daily_limit_amount: u64,
+    pub weekly_limit_amount: u64,
+    pub max_tx_per_day: u16,
+    pub per_tx_max_amount: u64,
+    pub blocked_categories: u32,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct DelegateParams {
+    pub spend_limit_amount: u64,
+    pub per_tx_max_amount: u64,
+    pub expires_ts: i64,
+    pub allowed_merchants: Vec<Pubkey>,
+}
+
+/// Uma parcela do cronograma: amortização de principal + juros.
+#[derive(Clone, Copy, Default)]
+pub struct InstallmentSplit {
+    pub principal_amount: u64,
+    pub interest_amount: u64,
+}
+
+impl InstallmentSplit {
+    pub fn total_amount(&self) -> u64 {
+        self.principal_amount + self.interest_amount
+    }
+}
+
+/// Quanto de um pagamento foi para cada saldo, na ordem da cascata.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct PaymentAllocation {
+    pub to_late_fees_amount: u64,
+    pub to_interest_amount: u64,
+    pub to_past_due_amount: u64,
+    pub to_statement_amount: u64,
+    pub to_cash_amount: u64,
+    pub to_future_amount: u64,
+}
+
+#[account]
+pub struct Statement {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub cycle_index: u32,
+    pub total_due_amount: u64,
+    pub min_payment_amount: u64,
+    pub due_date_ts: i64,
+    pub closed_ts: i64,
+    pub bump: u8,
+}
+
+/// Registro de uma compra; referência para estornos e disputas.
+#[account]
+pub struct OrderRecord {
+    pub order_id: [u8; 32],
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub mint: Pubkey,      // moeda da conta no momento da compra (a das notas)
+    pub authority: Pubkey, // quem assinou pela conta (owner ou delegate)
+    pub amount: u64,
+    pub interest_amount: u64,     // juros totais das parcelas (tabela Price)
+    pub merchant_net_amount: u64, // quanto o lojista recebe no total (após desconto, se absorvido)
+    pub installments: u8,
+    pub plan: u8,         // InstallmentPlan
+    pub first_cycle: u32, // ciclo (cycle_count) em que a parcela 0 é faturada
+    pub created_ts: i64,
+    pub status: u8,         // OrderStatus
+    pub refunded_amount: u64, // total estornado (valor de face das notas)
+    pub disputed_ts: i64,   // abertura da disputa (0 = nunca contestado)
+    pub dispute_deadline_ts: i64,
+    pub disputed_notes_amount: [u64; MAX_INSTALLMENTS], // retido por parcela durante a disputa
+    pub merchant_debt_amount: u64, // recompras do cofre em disputas perdidas; o lojista devolve
+    pub bump: u8,
+}
+
+impl OrderRecord {
+    /// Valor de face total das notas: principal + juros.
+    pub fn financed_amount(&self) -> u64 {
+        self.amount.saturating_add(self.interest_amount)
+    }
+
+    /// Parcela do principal correspondente a `refunded_amount` do valor de face.
+    pub fn principal_share_amount(&self, refunded_amount: u64) -> u64 {
+        let financed_amount = self.financed_amount();
+        if financed_amount == 0 {
+            return 0;
+        }
+        (self.amount as u128 * refunded_amount.min(financed_amount) as u128
+            / financed_amount as u128) as u64
+    }
+}
+
+/// Oferta de renegociação do admin para uma conta (PDA `[b"restructure", owner]`).
+#[account]
+pub struct RestructureOffer {
+    pub owner: Pubkey,
+    pub rate_bps_monthly: u32,
+    pub installments: u8,
+    pub expires_ts: i64,
+    pub bump: u8,
+}
+
+#[account]
+pub struct Config {
+    pub min_hf_bps_for_new_charges: u32,
+    pub min_hf_bps_for_withdraw: u32,
+    pub penalty_rate_bps_daily: u32,
+    pub late_fee_bps: u32,
+    pub grace_volatile_days: u8, // carência com colateral pump (volátil)
+    pub grace_any_days: u8,
+    pub default_after_days: u16, // dias após a carência até o default
+    pub min_payment_bps: u32,
+    pub min_payment_floor_usdc: u64,
+    pub max_price_age_secs: i64,
+    pub liquidation_bonus_bps: u32,
+    pub close_factor_bps: u32, // fração máxima da dívida quitável por liquidação
+    pub config_delay_secs: i64, // timelock de propose -> apply
+    pub risk_authority: Pubkey, // pode congelar/descongelar contas além do admin
+    pub score_tier_caps_usdc: [u64; 4], // teto de limite por faixa de score (0 = sem teto)
+    pub installment_rate_bps_monthly: u32, // juros do parcelado com juros (tabela Price)
+    pub merchant_discount_rate_bps_monthly: u32, // desconto do lojista no parcelado sem juros
+    pub dispute_window_days: u16, // prazo para contestar após a compra
+    pub dispute_resolution_days: u16, // prazo do admin; depois dele o comprador vence
+    pub cash_pool: Pubkey,     // pool do advance_pool que financia saques
+    pub cash_rate_bps_daily: u32, // juros diários do saque em dinheiro
+    pub min_hf_bps_for_cash: u32,
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+    pub pending_params: ConfigParams,
+    pub pending_eta: i64,  // 0 = sem proposta pendente
+    pub usdc_mint: Pubkey, // unidade de conta do colateral e dos parâmetros globais
+    pub vault_authority_bump: u8,
+    pub bump: u8,
+    pub settlement_mints: [SettlementMint; MAX_SETTLEMENT_MINTS], // moedas aceitas
+    pub settlement_mint_count: u8,
+}
+
+/// Stablecoin aceita como moeda da conta. Os campos `_amount` de contas, pedidos e faturas
+/// estão em unidades base da moeda da conta; os `_usdc` (colateral, parâmetros globais), em USDC.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct SettlementMint {
+    pub mint: Pubkey,
+    pub decimals: u8,
+    pub enabled: bool, // desabilitada: sem novas contas nem compras; pagamentos seguem
+}
+
+impl SettlementMint {
+    /// Entrada do próprio USDC.
+    pub fn usdc(mint: Pubkey, decimals: u8) -> Self {
+        Self {
+            mint,
+            decimals,
+            enabled: true,
+        }
+    }
+}
+
+/// Cotação em USDC de uma moeda de liquidação (ver `Config::settlement_quote`).
+#[derive(Clone, Copy)]
+pub struct SettlementQuote {
+    pub decimals: u8,
+    pub usd_rate_e6: u64, // valor de 1 unidade (10^decimals) em USDC; 1_000_000 = paridade
+    pub updated_ts: i64,  // última cotação; sujeita a `max_price_age_secs`
+}
+
+impl SettlementQuote {
+    /// USDC, unidade de conta: paridade fixa, nunca envelhece.
+    pub fn parity(decimals: u8) -> Self {
+        Self {
+            decimals,
+            usd_rate_e6: 1_000_000,
+            updated_ts: i64::MAX,
+        }
+    }
+
+    /// Unidades base da moeda -> USDC (6 casas).
+    pub fn to_usdc(&self, amount: u64) -> u64 {
+        (amount as u128 * self.usd_rate_e6 as u128 / 10u128.pow(self.decimals as u32))
+            .min(u64::MAX as u128) as u64
+    }
+
+    /// USDC (6 casas) -> unidades base da moeda.
+    pub fn from_usdc(&self, amount_usdc: u64) -> u64 {
+        (amount_usdc as u128 * 10u128.pow(self.decimals as u32) / self.usd_rate_e6.max(1) as u128)
+            .min(u64::MAX as u128) as u64
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct ConfigParams {
+    pub min_hf_bps_for_new_charges: u32,
+    pub min_hf_bps_for_withdraw: u32,
+    pub penalty_rate_bps_daily: u32,
+    pub late_fee_bps: u32,
+    pub grace_volatile_days: u8,
+    pub grace_any_days: u8,
+    pub default_after_days: u16,
+    pub min_payment_bps: u32,
+    pub min_payment_floor_usdc: u64,
+    pub max_price_age_secs: i64,
+    pub liquidation_bonus_bps: u32,
+    pub close_factor_bps: u32,
+    pub config_delay_secs: i64,
+    pub risk_authority: Pubkey,
+    pub score_tier_caps_usdc: [u64; 4],
+    pub installment_rate_bps_monthly: u32,
+    pub merchant_discount_rate_bps_monthly: u32,
+    pub dispute_window_days: u16,
+    pub dispute_resolution_days: u16,
+    pub cash_pool: Pubkey,
+    pub cash_rate_bps_daily: u32,
+    pub min_hf_bps_for_cash: u32,
+}
+
+impl ConfigParams {
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.close_factor_bps <= 10_000 && self.config_delay_secs >= 0,
+            CreditError::InvalidConfig
+        );
+        // Saque em dinheiro exige HF estritamente maior que o de compras
+        require!(
+            self.min_hf_bps_for_cash > self.min_hf_bps_for_new_charges,
+            CreditError::InvalidConfig
+        );
+        Ok(())
+    }
+}
+
+impl Config {
+    pub fn apply(&mut self, params: &ConfigParams) {
+        self.min_hf_bps_for_new_charges = params.min_hf_bps_for_new_charges;
+        self.min_hf_bps_for_withdraw = params.min_hf_bps_for_withdraw;
+        self.penalty_rate_bps_daily = params.penalty_rate_bps_daily;
+        self.late_fee_bps = params.late_fee_bps;
+        self.grace_volatile_days = params.grace_volatile_days;
+        self.grace_any_days = params.grace_any_days;
+        self.default_after_days = params.default_after_days;
+        self.min_payment_bps = params.min_payment_bps;
+        self.min_payment_floor_usdc = params.min_payment_floor_usdc;
+        self.max_price_age_secs = params.max_price_age_secs;
+        self.liquidation_bonus_bps = params.liquidation_bonus_bps;
+        self.close_factor_bps = params.close_factor_bps;
+        self.config_delay_secs = params.config_delay_secs;
+        self.risk_authority = params.risk_authority;
+        self.score_tier_caps_usdc = params.score_tier_caps_usdc;
+        self.installment_rate_bps_monthly = params.installment_rate_bps_monthly;
+        self.merchant_discount_rate_bps_monthly = params.merchant_discount_rate_bps_monthly;
+        self.dispute_window_days = params.dispute_window_days;
+        self.dispute_resolution_days = params.dispute_resolution_days;
+        self.cash_pool = params.cash_pool;
+        self.cash_rate_bps_daily = params.cash_rate_bps_daily;
+        self.min_hf_bps_for_cash = params.min_hf_bps_for_cash;
+    }
+
+    /// Moeda de liquidação `mint` da lista de permitidas.
+    pub fn settlement_mint(&self, mint: &Pubkey) -> Result<&SettlementMint> {
+        self.settlement_mints[..self.settlement_mint_count as usize]
+            .iter()
+            .find(|m| m.mint == *mint)
+            .ok_or_else(|| error!(CreditError::MintNotAllowed))
+    }
+
+    /// Cotação da moeda de liquidação `mint`: paridade para o USDC; para as demais, o
+    /// `PriceAccount` da moeda no collateral_vault (`[b"price", mint]`), mesmo antigo.
+    pub fn settlement_quote(
+        &self,
+        mint: &Pubkey,
+        price: Option<&PriceAccount>,
+    ) -> Result<SettlementQuote> {
+        let m = self.settlement_mint(mint)?;
+        if m.mint == self.usdc_mint {
+            return Ok(SettlementQuote::parity(m.decimals));
+        }
+        let price = price.ok_or(CreditError::SettlementPriceMissing)?;
+        require!(
+            price.mint == m.mint && price.price_usdc_6 > 0,
+            CreditError::SettlementPriceMissing
+        );
+        Ok(SettlementQuote {
+            decimals: m.decimals,
+            usd_rate_e6: price.price_usdc_6,
+            updated_ts: price.last_ts,
+        })
+    }
+
+    /// Como `settlement_quote`, exigindo cotação recente para avaliação de risco.
+    pub fn settlement_rate(
+        &self,
+        mint: &Pubkey,
+        price: Option<&PriceAccount>,
+        now: i64,
+    ) -> Result<SettlementQuote> {
+        let quote = self.settlement_quote(mint, price)?;
+        require!(
+            self.max_price_age_secs == 0
+                || now.saturating_sub(quote.updated_ts) <= self.max_price_age_secs,
+            CreditError::StaleSettlementRate
+        );
+        Ok(quote)
+    }
+
+    pub fn is_risk_authority(&self, key: &Pubkey) -> bool {
+        *key == self.admin
+            || (self.risk_authority != Pubkey::default() && *key == self.risk_authority)
+    }
+}
+
+// ---------------- Contexts ----------------
+#[derive(Accounts)]
+pub struct InitConfig<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + std::mem::size_of::<Config>(),
+        seeds = [b"credit_config"],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+    pub usdc_mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: PDA sem dados, autoridade dos cofres do protocolo
+    #[account(seeds = [b"credit_vault"], bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    /// ATA do PDA `vault_authority` que recebe os pagamentos
+    #[account(
+        init,
+        payer = admin,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = vault_authority,
+        associated_token::token_program = token_program
+    )]
+    pub usdc_vault: InterfaceAccount<'info, TokenAccount>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AdminConfig<'info> {
+    pub admin: Signer<'info>,
+    #[account(mut, seeds = [b"credit_config"], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyConfigUpdate<'info> {
+    #[account(mut, seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    pub new_admin: Signer<'info>,
+    #[account(mut, seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct OpenAccount<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + std::mem::size_of::<CreditAccount>(),
+        seeds = [b"credit", owner.key().as_ref()],
+        bump
+    )]
+    pub credit_account: Account<'info, CreditAccount>,
+    #[account(seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    /// Moeda da conta; precisa estar na lista do Config
+    pub mint: InterfaceAccount<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetSettlementMint<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(mut, seeds = [b"credit_config"], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, Config>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: PDA sem dados, autoridade dos cofres do protocolo
+    #[account(seeds = [b"credit_vault"], bump = config.vault_authority_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    /// Cofre do protocolo nesta moeda
+    #[account(
+        init_if_needed,
+        payer = admin,
+        associated_token::mint = mint,
+        associated_token::authority = vault_authority,
+        associated_token::token_program = token_program
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetLimit<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub config: Account<'info, Config>,
+    #[account(mut, has_one = owner)]
+    pub credit_account: Account<'info, CreditAccount>,
+    /// CHECK: only used for has_one check
+    pub owner: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(key: Pubkey)]
+pub struct AddDelegate<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
+    pub credit_account: Account<'info, CreditAccount>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + std::mem::size_of::<Delegate>(),
+        seeds = [b"delegate", credit_account.key().as_ref(), key.as_ref()],
+        bump
+    )]
+    pub delegate: Account<'info, Delegate>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetSpendControls<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
+    pub credit_account: Account<'info, CreditAccount>,
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + std::mem::size_of::<SpendControls>(),
+        seeds = [b"controls", owner.key().as_ref()],
+        bump
+    )]
+    pub spend_controls: Account<'info, SpendControls>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetMerchantCategory<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(seeds = [b"credit_config"], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, Config>,
+    /// CHECK: só a chave do lojista
+    pub merchant: UncheckedAccount<'info>,
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = 8 + std::mem::size_of::<MerchantProfile>(),
+        seeds = [b"merchant", merchant.key().as_ref()],
+        bump
+    )]
+    pub merchant_profile: Account<'info, MerchantProfile>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClearSpendControls<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
+    pub credit_account: Account<'info, CreditAccount>,
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"controls", owner.key().as_ref()],
+        bump = spend_controls.bump,
+        has_one = owner
+    )]
+    pub spend_controls: Account<'info, SpendControls>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateDelegate<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
+    pub credit_account: Account<'info, CreditAccount>,
+    #[account(
+        mut,
+        seeds = [b"delegate", credit_account.key().as_ref(), delegate.key.as_ref()],
+        bump = delegate.bump,
+        has_one = credit_account
+    )]
+    pub delegate: Account<'info, Delegate>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, installments: u8, order_id: [u8; 32])]
+pub struct Charge<'info> {
+    /// Owner da conta ou chave de um cartão adicional (`delegate`)
+    pub authority: Signer<'info>,
+    /// Lojista: co-assina a compra e paga o rent das notas
+    #[account(mut)]
+    pub merchant: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
+    pub credit_account: Box<Account<'info, CreditAccount>>,
+    /// CHECK: only used for has_one check
+    pub owner: UncheckedAccount<'info>,
+    /// Obrigatório quando `authority` não é o owner
+    #[account(
+        mut,
+        seeds = [b"delegate", credit_account.key().as_ref(), authority.key().as_ref()],
+        bump = delegate.bump
+    )]
+    pub delegate: Option<Box<Account<'info, Delegate>>>,
+    /// Obrigatório quando a conta tem controles de gasto
+    #[account(
+        mut,
+        seeds = [b"controls", owner.key().as_ref()],
+        bump = spend_controls.bump
+    )]
+    pub spend_controls: Option<Box<Account<'info, SpendControls>>>,
+    /// Obrigatório quando os controles de gasto bloqueiam categorias
+    #[account(
+        seeds = [b"merchant", merchant.key().as_ref()],
+        bump = merchant_profile.bump
+    )]
+    pub merchant_profile: Option<Box<Account<'info, MerchantProfile>>>,
+    #[account(seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    /// Cotação da moeda da conta no collateral_vault; dispensável quando ela é o USDC
+    #[account(
+        seeds = [b"price", credit_account.mint.as_ref()],
+        bump = settlement_price.bump,
+        seeds::program = collateral_vault::ID
+    )]
+    pub settlement_price: Option<Box<Account<'info, PriceAccount>>>,
+    #[account(
+        init,
+        payer = merchant,
+        space = 8 + std::mem::size_of::<OrderRecord>(),
+        seeds = [b"order", order_id.as_ref()],
+        bump
+    )]
+    pub order_record: Box<Account<'info, OrderRecord>>,
+    /// CHECK: PDA sem dados, emissor das notas no receivables
+    #[account(seeds = [b"credit_vault"], bump = config.vault_authority_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    pub receivables_program: Program<'info, Receivables>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Refund<'info> {
+    /// Lojista da compra; devolve o líquido das notas antecipadas
+    pub merchant: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"order", order_record.order_id.as_ref()],
+        bump = order_record.bump,
+        has_one = merchant
+    )]
+    pub order_record: Box<Account<'info, OrderRecord>>,
+    #[account(
+        mut,
+        seeds = [b"credit", order_record.owner.as_ref()],
+        bump = credit_account.bump
+    )]
+    pub credit_account: Box<Account<'info, CreditAccount>>,
+    /// CHECK: cartão adicional que fez a compra (`[b"delegate", credit_account, authority]`);
+    /// vazio se a compra foi do owner ou o delegate já foi revogado
+    #[account(
+        mut,
+        seeds = [b"delegate", credit_account.key().as_ref(), order_record.authority.as_ref()],
+        bump
+    )]
+    pub delegate: UncheckedAccount<'info>,
+    #[account(seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(address = order_record.mint @ CreditError::MintMismatch)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    /// ATA do lojista (fonte da recompra de notas antecipadas)
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = merchant,
+        associated_token::token_program = token_program
+    )]
+    pub merchant_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: PDA sem dados, emissor das notas no receivables
+    #[account(seeds = [b"credit_vault"], bump = config.vault_authority_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    pub receivables_program: Program<'info, Receivables>,
+    pub token_program: Interface<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct OpenDispute<'info> {
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
+    pub credit_account: Box<Account<'info, CreditAccount>>,
+    #[account(
+        mut,
+        seeds = [b"order", order_record.order_id.as_ref()],
+        bump = order_record.bump,
+        has_one = owner
+    )]
+    pub order_record: Box<Account<'info, OrderRecord>>,
+    #[account(seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    /// Cotação da moeda da conta no collateral_vault; dispensável quando ela é o USDC
+    #[account(
+        seeds = [b"price", credit_account.mint.as_ref()],
+        bump = settlement_price.bump,
+        seeds::program = collateral_vault::ID
+    )]
+    pub settlement_price: Option<Box<Account<'info, PriceAccount>>>,
+    /// CHECK: PDA sem dados, emissor das notas no receivables
+    #[account(seeds = [b"credit_vault"], bump = config.vault_authority_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    pub receivables_program: Program<'info, Receivables>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveDispute<'info> {
+    /// Admin até o prazo; qualquer um depois dele
+    pub resolver: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"order", order_record.order_id.as_ref()],
+        bump = order_record.bump
+    )]
+    pub order_record: Box<Account<'info, OrderRecord>>,
+    #[account(
+        mut,
+        seeds = [b"credit", order_record.owner.as_ref()],
+        bump = credit_account.bump
+    )]
+    pub credit_account: Box<Account<'info, CreditAccount>>,
+    /// CHECK: cartão adicional que fez a compra (`[b"delegate", credit_account, authority]`);
+    /// vazio se a compra foi do owner ou o delegate já foi revogado
+    #[account(
+        mut,
+        seeds = [b"delegate", credit_account.key().as_ref(), order_record.authority.as_ref()],
+        bump
+    )]
+    pub delegate: UncheckedAccount<'info>,
+    #[account(seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    /// Cotação da moeda da conta no collateral_vault; dispensável quando ela é o USDC
+    #[account(
+        seeds = [b"price", credit_account.mint.as_ref()],
+        bump = settlement_price.bump,
+        seeds::program = collateral_vault::ID
+    )]
+    pub settlement_price: Option<Box<Account<'info, PriceAccount>>>,
+    /// CHECK: PDA sem dados, emissor das notas no receivables
+    #[account(seeds = [b"credit_vault"], bump = config.vault_authority_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(address = order_record.mint @ CreditError::MintMismatch)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    /// Cofre do protocolo (fonte da recompra de notas antecipadas)
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault_authority,
+        associated_token::token_program = token_program
+    )]
+    pub vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub receivables_program: Program<'info, Receivables>,
+    pub token_program: Interface<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RepayMerchantDebt<'info> {
+    pub merchant: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"order", order_record.order_id.as_ref()],
+        bump = order_record.bump,
+        has_one = merchant
+    )]
+    pub order_record: Box<Account<'info, OrderRecord>>,
+    #[account(seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(address = order_record.mint @ CreditError::MintMismatch)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    /// ATA do lojista (fonte)
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = merchant,
+        associated_token::token_program = token_program
+    )]
+    pub merchant_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: PDA sem dados, autoridade do cofre
+    #[account(seeds = [b"credit_vault"], bump = config.vault_authority_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    /// Cofre do protocolo (destino)
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault_authority,
+        associated_token::token_program = token_program
+    )]
+    pub vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CashAdvance<'info> {
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
+    pub credit_account: Box<Account<'info, CreditAccount>>,
+    #[account(
+        seeds = [b"credit_config"],
+        bump = config.bump,
+        constraint = config.cash_pool == pool.key() @ CreditError::Unauthorized
+    )]
+    pub config: Box<Account<'info, Config>>,
+    /// Cotação da moeda da conta no collateral_vault; dispensável quando ela é o USDC
+    #[account(
+        seeds = [b"price", credit_account.mint.as_ref()],
+        bump = settlement_price.bump,
+        seeds::program = collateral_vault::ID
+    )]
+    pub settlement_price: Option<Box<Account<'info, PriceAccount>>>,
+    #[account(address = credit_account.mint @ CreditError::MintMismatch)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    /// ATA do owner (destino do saque)
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program
+    )]
+    pub owner_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: PDA sem dados, autoriza o saque no advance_pool
+    #[account(seeds = [b"credit_vault"], bump = config.vault_authority_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub pool: Box<Account<'info, Pool>>,
+    /// CHECK: cofre do pool, validado pelo advance_pool
+    #[account(mut)]
+    pub pool_vault: UncheckedAccount<'info>,
+    pub advance_pool_program: Program<'info, AdvancePool>,
+    pub token_program: Interface<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ReturnCashToPool<'info> {
+    #[account(
+        mut,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
+    pub credit_account: Box<Account<'info, CreditAccount>>,
+    /// CHECK: only used for has_one check
+    pub owner: UncheckedAccount<'info>,
+    #[account(
+        seeds = [b"credit_config"],
+        bump = config.bump,
+        constraint = config.cash_pool == pool.key() @ CreditError::Unauthorized
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(address = credit_account.mint @ CreditError::MintMismatch)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: PDA sem dados, autoridade do cofre e da devolução no advance_pool
+    #[account(seeds = [b"credit_vault"], bump = config.vault_authority_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    /// Cofre do protocolo (fonte)
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault_authority,
+        associated_token::token_program = token_program
+    )]
+    pub vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub pool: Box<Account<'info, Pool>>,
+    /// CHECK: cofre do pool, validado pelo advance_pool
+    #[account(mut)]
+    pub pool_vault: UncheckedAccount<'info>,
+    pub advance_pool_program: Program<'info, AdvancePool>,
+    pub token_program: Interface<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetAutopay<'info> {
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
+    pub credit_account: Account<'info, CreditAccount>,
+    #[account(seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(address = credit_account.mint @ CreditError::MintMismatch)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program
+    )]
+    pub owner_ata: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: PDA sem dados, delegate do débito automático
+    #[account(seeds = [b"credit_vault"], bump = config.vault_authority_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RunAutopay<'info> {
+    #[account(
+        mut,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
+    pub credit_account: Box<Account<'info, CreditAccount>>,
+    /// CHECK: only used for has_one check
+    pub owner: UncheckedAccount<'info>,
+    #[account(seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(address = credit_account.mint @ CreditError::MintMismatch)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    /// ATA do owner (fonte), com o PDA como delegate
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program
+    )]
+    pub owner_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: PDA sem dados, delegate do ATA e autoridade do cofre
+    #[account(seeds = [b"credit_vault"], bump = config.vault_authority_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault_authority,
+        associated_token::token_program = token_program
+    )]
+    pub vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeRestructure<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(seeds = [b"credit_config"], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, Config>,
+    #[account(seeds = [b"credit", owner.key().as_ref()], bump = credit_account.bump, has_one = owner)]
+    pub credit_account: Account<'info, CreditAccount>,
+    /// CHECK: only used for has_one check
+    pub owner: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + std::mem::size_of::<RestructureOffer>(),
+        seeds = [b"restructure", owner.key().as_ref()],
+        bump
+    )]
+    pub offer: Account<'info, RestructureOffer>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelRestructure<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(seeds = [b"credit_config"], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        close = admin,
+        seeds = [b"restructure", offer.owner.as_ref()],
+        bump = offer.bump
+    )]
+    pub offer: Account<'info, RestructureOffer>,
+}
+
+#[derive(Accounts)]
+#[instruction(order_id: [u8; 32])]
+pub struct AcceptRestructure<'info> {
+    /// Owner aceita e paga o rent das novas notas
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
+    pub credit_account: Box<Account<'info, CreditAccount>>,
+    #[account(seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    /// CHECK: recebe o rent da oferta (quem pagou)
+    #[account(mut, address = config.admin)]
+    pub admin: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        close = admin,
+        seeds = [b"restructure", owner.key().as_ref()],
+        bump = offer.bump,
+        has_one = owner
+    )]
+    pub offer: Box<Account<'info, RestructureOffer>>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + std::mem::size_of::<OrderRecord>(),
+        seeds = [b"order", order_id.as_ref()],
+        bump
+    )]
+    pub order_record: Box<Account<'info, OrderRecord>>,
+    /// CHECK: PDA sem dados, emissor das notas no receivables e autoridade do cofre
+    #[account(seeds = [b"credit_vault"], bump = config.vault_authority_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(address = credit_account.mint @ CreditError::MintMismatch)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    /// Cofre do protocolo (fonte da recompra de notas antecipadas)
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault_authority,
+        associated_token::token_program = token_program
+    )]
+    pub vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub receivables_program: Program<'info, Receivables>,
+    pub token_program: Interface<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseAccount<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
+    pub credit_account: Box<Account<'info, CreditAccount>>,
+    /// Obrigatório quando a conta tem controles de gasto; o rent volta ao owner
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"controls", owner.key().as_ref()],
+        bump = spend_controls.bump,
+        has_one = owner
+    )]
+    pub spend_controls: Option<Box<Account<'info, SpendControls>>>,
+    #[account(seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(address = credit_account.mint @ CreditError::MintMismatch)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    /// ATA do owner (destino do crédito restante)
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program
+    )]
+    pub owner_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: PDA sem dados, autoridade do cofre
+    #[account(seeds = [b"credit_vault"], bump = config.vault_authority_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault_authority,
+        associated_token::token_program = token_program
+    )]
+    pub vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ForceCloseAccount<'info> {
+    pub admin: Signer<'info>,
+    #[account(seeds = [b"credit_config"], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
+    pub credit_account: Account<'info, CreditAccount>,
+    /// CHECK: only used for has_one check
+    pub owner: UncheckedAccount<'info>,
+    /// CHECK: PDA sem dados, emissor das notas no receivables e autoridade no advance_pool
+    #[account(seeds = [b"credit_vault"], bump = config.vault_authority_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    /// Pool que financiou os saques em dinheiro; recebe a baixa do principal em aberto
+    #[account(mut, address = config.cash_pool @ CreditError::Unauthorized)]
+    pub pool: Box<Account<'info, Pool>>,
+    pub receivables_program: Program<'info, Receivables>,
+    pub advance_pool_program: Program<'info, AdvancePool>,
+}
+
+#[derive(Accounts)]
+pub struct Repay<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
+    pub credit_account: Account<'info, CreditAccount>,
+    /// CHECK: only used for has_one check
+    pub owner: UncheckedAccount<'info>,
+    #[account(seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    #[account(address = credit_account.mint @ CreditError::MintMismatch)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    /// ATA do pagador (fonte)
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = payer,
+        associated_token::token_program = token_program
+    )]
+    pub payer_ata: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: PDA sem dados, autoridade do cofre
+    #[account(seeds = [b"credit_vault"], bump = config.vault_authority_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    /// Cofre do protocolo (destino)
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault_authority,
+        associated_token::token_program = token_program
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct StatementClose<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
+    pub credit_account: Account<'info, CreditAccount>,
+    /// CHECK: only used for has_one check
+    pub owner: UncheckedAccount<'info>,
+    #[account(seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    /// Cotação da moeda da conta no collateral_vault; dispensável quando ela é o USDC
+    #[account(
+        seeds = [b"price", credit_account.mint.as_ref()],
+        bump = settlement_price.bump,
+        seeds::program = collateral_vault::ID
+    )]
+    pub settlement_price: Option<Box<Account<'info, PriceAccount>>>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + std::mem::size_of::<Statement>(),
+        seeds = [
+            b"statement",
+            owner.key().as_ref(),
+            &credit_account.opened_ts.to_le_bytes(),
+            &credit_account.cycle_count.saturating_add(1).to_le_bytes()
+        ],
+        bump
+    )]
+    pub statement: Account<'info, Statement>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AccountCrank<'info> {
+    #[account(
+        mut,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
+    pub credit_account: Account<'info, CreditAccount>,
+    /// CHECK: only used for has_one check
+    pub owner: UncheckedAccount<'info>,
+    #[account(seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    /// Cotação da moeda da conta no collateral_vault; dispensável quando ela é o USDC
+    #[account(
+        seeds = [b"price", credit_account.mint.as_ref()],
+        bump = settlement_price.bump,
+        seeds::program = collateral_vault::ID
+    )]
+    pub settlement_price: Option<Box<Account<'info, PriceAccount>>>,
+}
+
+#[derive(Accounts)]
+pub struct RefreshHealth<'info> {
+    #[account(
+        mut,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
+    pub credit_account: Account<'info, CreditAccount>,
+    /// CHECK: only used for has_one check
+    pub owner: UncheckedAccount<'info>,
+    #[account(seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+    /// Cotação da moeda da conta no collateral_vault; dispensável quando ela é o USDC
+    #[account(
+        seeds = [b"price", credit_account.mint.as_ref()],
+        bump = settlement_price.bump,
+        seeds::program = collateral_vault::ID
+    )]
+    pub settlement_price: Option<Box<Account<'info, PriceAccount>>>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawCollateral<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    /// CHECK: PDA `[b"credit", owner]`; pode não existir (owner sem conta de crédito)
+    #[account(mut, seeds = [b"credit", owner.key().as_ref()], bump)]
+    pub credit_account: UncheckedAccount<'info>,
+    #[account(seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    /// Cotação da moeda da conta no collateral_vault (conferida contra a moeda no handler);
+    /// dispensável quando ela é o USDC
+    pub settlement_price: Option<Box<Account<'info, PriceAccount>>>,
+    /// CHECK: PDA sem dados, signer da CPI de saque
+    #[account(seeds = [b"credit_vault"], bump = config.vault_authority_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+
+    // --- Cofre do collateral_vault -> owner (validado na CPI)
+    pub vault_config: Box<Account<'info, VaultConfig>>,
+    #[account(mut)]
+    pub collateral_vault: Box<Account<'info, Vault>>,
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub position: Box<Account<'info, Position>>,
+    pub price_account: Box<Account<'info, PriceAccount>>,
     #[account(mut)]
+    pub vault_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub owner_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub collateral_vault_program: Program<'info, CollateralVault>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Liquidate<'info> {
+    #[account(mut)]
+    pub liquidator: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
+    pub credit_account: Box<Account<'info, CreditAccount>>,
+    /// CHECK: only used for has_one check
+    pub owner: UncheckedAccount<'info>,
+    #[account(seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Box<Account<'info, Config>>,
+    /// Cotação da moeda da conta no collateral_vault; dispensável quando ela é o USDC
+    #[account(
+        seeds = [b"price", credit_account.mint.as_ref()],
+        bump = settlement_price.bump,
+        seeds::program = collateral_vault::ID
+    )]
+    pub settlement_price: Option<Box<Account<'info, PriceAccount>>>,
+
+    // --- Lado da dívida: liquidante -> cofre do protocolo, na moeda da conta
+    #[account(address = credit_account.mint @ CreditError::MintMismatch)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = liquidator,
+        associated_token::token_program = token_program
+    )]
+    pub liquidator_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: PDA sem dados, autoridade do cofre e signer da CPI de liquidação
+    #[account(seeds = [b"credit_vault"], bump = config.vault_authority_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault_authority,
+        associated_token::token_program = token_program
+    )]
+    pub vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    // --- Lado colateral: cofre do collateral_vault -> liquidante (validado na CPI)
+    pub vault_config: Box<Account<'info, VaultConfig>>,
+    pub collateral_vault: Box<Account<'info, Vault>>,
+    pub collateral_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub position: Box<Account<'info, Position>>,
+    pub price_account: Box<Account<'info, PriceAccount>>,
+    #[account(mut)]
+    pub vault_collateral_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub liquidator_collateral_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub collateral_vault_program: Program<'info, CollateralVault>,
+    pub token_program: Interface<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct Freeze<'info> {
+    #[account(constraint = config.is_risk_authority(&authority.key()) @ CreditError::Unauthorized)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"credit", owner.key().as_ref()],
+        bump = credit_account.bump,
+        has_one = owner
+    )]
     pub credit_account: Account<'info, CreditAccount>,
+    /// CHECK: only used for has_one check
+    pub owner: UncheckedAccount<'info>,
+    #[account(seeds = [b"credit_config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+// ---------------- Events ----------------
+#[event]
+pub struct ConfigUpdateProposed {
+    pub params: ConfigParams,
+    pub eta: i64,
+}
+
+#[event]
+pub struct ConfigUpdateCancelled {}
+
+#[event]
+pub struct ConfigUpdated {
+    pub params: ConfigParams,
+}
+
+#[event]
+pub struct SettlementMintUpdated {
+    pub mint: Pubkey,
+    pub decimals: u8,
+    pub enabled: bool,
+}
+
+#[event]
+pub struct AdminProposed {
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+}
+
+#[event]
+pub struct AdminChanged {
+    pub old_admin: Pubkey,
+    pub new_admin: Pubkey,
+}
+
+#[event]
+pub struct ChargeAuthorized {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub authority: Pubkey,
+    pub amount: u64,
+    pub interest_amount: u64,
+    pub merchant_net_amount: u64,
+    pub installments: u8,
+    pub plan: u8,
+    pub order_id: [u8; 32],
+}
+
+#[event]
+pub struct ChargeRefunded {
+    pub order_id: [u8; 32],
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub amount: u64,
+    pub refunded_total_amount: u64,
+    pub clawback_amount: u64, // líquido devolvido aos beneficiários de notas antecipadas
+    pub credit_amount: u64,   // parte já paga, creditada na conta
+    pub ts: i64,
 }
 
-// ---------------- Events ----------------
 #[event]
-pub struct ChargeAuthorized {
+pub struct DisputeOpened {
+    pub order_id: [u8; 32],
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub disputed_amount: u64,
+    pub deadline_ts: i64,
+}
+
+#[event]
+pub struct DisputeResolved {
+    pub order_id: [u8; 32],
+    pub owner: Pubkey,
+    pub resolver: Pubkey,
+    pub buyer_wins: bool,
+    pub released_amount: u64,
+    pub credit_amount: u64,
+    pub buyback_amount: u64, // líquido recomprado pelo cofre (dívida do lojista)
+    pub ts: i64,
+}
+
+#[event]
+pub struct DelegateUpdated {
+    pub owner: Pubkey,
+    pub key: Pubkey,
+    pub spend_limit_amount: u64,
+    pub per_tx_max_amount: u64,
+    pub expires_ts: i64,
+}
+
+#[event]
+pub struct SpendControlsUpdated {
+    pub owner: Pubkey,
+    pub params: SpendControlsParams,
+}
+
+#[event]
+pub struct MerchantCategorySet {
+    pub merchant: Pubkey,
+    pub category: u8,
+}
+
+#[event]
+pub struct CashAdvanced {
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub cash_balance_amount: u64,
+    pub ts: i64,
+}
+
+#[event]
+pub struct MerchantDebtRepaid {
+    pub order_id: [u8; 32],
+    pub merchant: Pubkey,
+    pub amount: u64,
+    pub remaining_amount: u64,
+}
+
+#[event]
+pub struct CashReturned {
     pub owner: Pubkey,
-    pub amount_usdc: u64,
+    pub pool: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct CashInterestAccrued {
+    pub owner: Pubkey,
+    pub interest_amount: u64,
+    pub days: u32,
+    pub ts: i64,
+}
+
+#[event]
+pub struct AutopayUpdated {
+    pub owner: Pubkey,
+    pub mode: u8,
+    pub cap_amount: u64,
+    pub allowance_amount: u64,
+}
+
+#[event]
+pub struct RestructureProposed {
+    pub owner: Pubkey,
+    pub rate_bps_monthly: u32,
+    pub installments: u8,
+    pub expires_ts: i64,
+}
+
+#[event]
+pub struct AccountRestructured {
+    pub owner: Pubkey,
+    pub order_id: [u8; 32],
+    pub principal_amount: u64,
+    pub interest_amount: u64,
     pub installments: u8,
+    pub cancelled_notes_amount: u64,
+    pub buyback_amount: u64, // líquido pago aos beneficiários das notas canceladas
+    pub ts: i64,
+}
+
+#[event]
+pub struct AccountClosed {
+    pub owner: Pubkey,
+    pub authority: Pubkey,
+    pub refund_amount: u64,      // crédito de estornos devolvido ao owner
+    pub charged_off_amount: u64, // saldo baixado como perda (force close)
+    pub ts: i64,
+}
+
+#[event]
+pub struct DelegateRevoked {
+    pub owner: Pubkey,
+    pub key: Pubkey,
+}
+
+#[event]
+pub struct InstallmentScheduled {
     pub order_id: [u8; 32],
+    pub index: u8,
+    pub principal_amount: u64,
+    pub interest_amount: u64,
+    pub due_ts: i64,
 }
 
 #[event]
 pub struct StatementClosed {
     pub owner: Pubkey,
-    pub cycle_id: [u8; 8],
-    pub total_due_usdc: u64,
-    pub min_payment_usdc: u64,
+    pub cycle_index: u32,
+    pub total_due_amount: u64,
+    pub min_payment_amount: u64,
     pub due_date_ts: i64,
 }
 
+#[event]
+pub struct AccountStatusChanged {
+    pub owner: Pubkey,
+    pub old_status: u8,
+    pub new_status: u8,
+    pub reason: u8,
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct InterestAccrued {
+    pub owner: Pubkey,
+    pub interest_amount: u64,
+    pub late_fee_amount: u64,
+    pub days: u32,
+    pub ts: i64,
+}
+
+#[event]
+pub struct HealthRefreshed {
+    pub owner: Pubkey,
+    pub collateral_value_usdc: u64,
+    pub weighted_collateral_usdc: u64,
+    pub owed_usdc: u64,
+    pub health_factor_bps: u32,
+    pub ts: i64,
+}
+
+#[event]
+pub struct Liquidated {
+    pub owner: Pubkey,
+    pub liquidator: Pubkey,
+    pub repaid_amount: u64,
+    pub collateral_mint: Pubkey,
+    pub seized_amount: u64,
+    pub seized_value_usdc: u64,
+    pub hf_before_bps: u32,
+    pub hf_after_bps: u32,
+}
+
+#[event]
+pub struct ScoreBandChanged {
+    pub owner: Pubkey,
+    pub old_band: u8,
+    pub new_band: u8,
+    pub score: u16,
+}
+
+#[event]
+pub struct LimitUpdated {
+    pub owner: Pubkey,
+    pub old_limit_amount: u64,
+    pub new_limit_amount: u64,
+    pub collateral_credit_usdc: u64,
+    pub score_band: u8,
+}
+
+#[event]
+pub struct PaymentAllocated {
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub allocation: PaymentAllocation,
+}
+
 #[event]
 pub struct PaymentPosted {
     pub owner: Pubkey,
-    pub amount_usdc: u64,
+    pub payer: Pubkey,
+    pub amount: u64,
+    pub remaining_used_amount: u64,
+    pub ts: i64,
 }
 
 // ---------------- Errors & Enums ----------------
@@ -244,6 +4103,136 @@ pub enum CreditError {
     UsedExceedsNewLimit,
     #[msg("Unauthorized")]
     Unauthorized,
+    #[msg("Invalid amount")]
+    InvalidAmount,
+    #[msg("Payment exceeds outstanding balance")]
+    Overpayment,
+    #[msg("Statement cycle has not ended yet")]
+    StatementNotDue,
+    #[msg("Invalid collateral accounts")]
+    InvalidCollateralAccounts,
+    #[msg("Collateral position listed twice")]
+    DuplicateCollateral,
+    #[msg("Collateral price is stale")]
+    StalePrice,
+    #[msg("Health factor is not below liquidation threshold")]
+    NotLiquidatable,
+    #[msg("Repay amount exceeds close factor")]
+    CloseFactorExceeded,
+    #[msg("Invalid config parameters")]
+    InvalidConfig,
+    #[msg("Note accounts do not match installments")]
+    NoteAccountsMismatch,
+    #[msg("No pending config update")]
+    NoPendingUpdate,
+    #[msg("Config timelock has not elapsed")]
+    TimelockNotElapsed,
+    #[msg("No pending admin")]
+    NoPendingAdmin,
+    #[msg("Account is not frozen")]
+    AccountNotFrozen,
+    #[msg("Limit is managed automatically")]
+    AutoLimitEnabled,
+    #[msg("Automatic limit is disabled")]
+    AutoLimitDisabled,
+    #[msg("Invalid installment plan")]
+    InvalidPlan,
+    #[msg("Refund exceeds the order's outstanding amount")]
+    RefundExceedsOrder,
+    #[msg("Invalid clawback token account")]
+    InvalidClawbackAccount,
+    #[msg("Order is under dispute")]
+    OrderDisputed,
+    #[msg("Order already refunded")]
+    OrderRefunded,
+    #[msg("Dispute window has closed")]
+    DisputeWindowClosed,
+    #[msg("Order was already disputed")]
+    DisputeAlreadyOpened,
+    #[msg("Order is not under dispute")]
+    NotDisputed,
+    #[msg("Invalid dispute outcome")]
+    InvalidOutcome,
+    #[msg("Too many allowed merchants")]
+    TooManyMerchants,
+    #[msg("Delegate has expired")]
+    DelegateExpired,
+    #[msg("Merchant not allowed for this delegate")]
+    MerchantNotAllowed,
+    #[msg("Charge exceeds delegate per-transaction max")]
+    DelegateTxCapExceeded,
+    #[msg("Charge exceeds delegate spending limit")]
+    DelegateLimitExceeded,
+    #[msg("Spend controls account required")]
+    SpendControlsMissing,
+    #[msg("Merchant category is blocked")]
+    CategoryBlocked,
+    #[msg("Charge exceeds per-transaction cap")]
+    PerTxCapExceeded,
+    #[msg("Daily transaction count exceeded")]
+    DailyTxCountExceeded,
+    #[msg("Daily spending limit exceeded")]
+    DailyLimitExceeded,
+    #[msg("Weekly spending limit exceeded")]
+    WeeklyLimitExceeded,
+    #[msg("Invalid autopay mode")]
+    InvalidAutopayMode,
+    #[msg("Autopay is disabled")]
+    AutopayDisabled,
+    #[msg("Statement is not due yet")]
+    AutopayNotDue,
+    #[msg("Autopay already ran for this statement")]
+    AutopayAlreadyRun,
+    #[msg("Nothing to collect via autopay")]
+    NothingToAutopay,
+    #[msg("Restructure offer has expired")]
+    RestructureExpired,
+    #[msg("Nothing overdue to restructure")]
+    NothingToRestructure,
+    #[msg("Note cannot be replaced by this restructure")]
+    InvalidRestructureNote,
+    #[msg("Account cannot be closed in its current status")]
+    AccountNotClosable,
+    #[msg("Account has an outstanding balance or open dispute")]
+    OutstandingBalance,
+    #[msg("Revoke all delegates first")]
+    DelegatesActive,
+    #[msg("Collateral is still deposited")]
+    CollateralLocked,
+    #[msg("Account is not in default")]
+    AccountNotDefaulted,
+    #[msg("Cash advance exceeds cash sub-limit")]
+    CashLimitExceeded,
+    #[msg("Mint is not an allowed settlement currency")]
+    MintNotAllowed,
+    #[msg("Mint does not match the account's settlement currency")]
+    MintMismatch,
+    #[msg("Too many settlement mints")]
+    TooManyMints,
+    #[msg("Health factor is stale; refresh it first")]
+    StaleHealthFactor,
+    #[msg("Invalid freeze reason")]
+    InvalidFreezeReason,
+    #[msg("Merchant has no registered category")]
+    MerchantNotRegistered,
+    #[msg("Invalid merchant category")]
+    InvalidCategory,
+    #[msg("Account cannot be frozen in its current status")]
+    AccountNotFreezable,
+    #[msg("Invalid account status")]
+    InvalidStatus,
+    #[msg("No repaid cash advance to return to the pool")]
+    NothingToReturn,
+    #[msg("Repaid cash advance must be returned to the pool first")]
+    CashNotReturned,
+    #[msg("Settlement mint rate is stale")]
+    StaleSettlementRate,
+    #[msg("Settlement mint has no price account")]
+    SettlementPriceMissing,
+    #[msg("Account was charged off; collateral stays locked")]
+    ChargedOff,
+    #[msg("Math overflow")]
+    MathOverflow,
 }
 
 #[repr(u8)]
@@ -251,4 +4240,755 @@ pub enum AccountStatus {
     Active = 0,
     SoftFrozen = 1,
     HardFrozen = 2,
+    Delinquent = 3, // mínimo em atraso além da carência; compras bloqueadas
+    Defaulted = 4,  // atraso além de `default_after_days`; não volta sozinha
+    Closed = 5,
+}
+
+impl TryFrom<u8> for AccountStatus {
+    type Error = Error;
+
+    fn try_from(code: u8) -> Result<Self> {
+        Ok(match code {
+            0 => AccountStatus::Active,
+            1 => AccountStatus::SoftFrozen,
+            2 => AccountStatus::HardFrozen,
+            3 => AccountStatus::Delinquent,
+            4 => AccountStatus::Defaulted,
+            5 => AccountStatus::Closed,
+            _ => return err!(CreditError::InvalidStatus),
+        })
+    }
+}
+
+#[repr(u8)]
+pub enum FreezeReason {
+    None = 0,
+    PastDue = 1,
+    Fraud = 2,
+    RiskReview = 3,
+    Compliance = 4,
+    OwnerRequest = 5,
+}
+
+impl TryFrom<u8> for FreezeReason {
+    type Error = Error;
+
+    fn try_from(code: u8) -> Result<Self> {
+        Ok(match code {
+            0 => FreezeReason::None,
+            1 => FreezeReason::PastDue,
+            2 => FreezeReason::Fraud,
+            3 => FreezeReason::RiskReview,
+            4 => FreezeReason::Compliance,
+            5 => FreezeReason::OwnerRequest,
+            _ => return err!(CreditError::InvalidFreezeReason),
+        })
+    }
+}
+
+impl FreezeReason {
+    /// Código válido para congelar uma conta: conhecido e diferente de `None`.
+    pub fn freeze_code(code: u8) -> Result<u8> {
+        match FreezeReason::try_from(code)? {
+            FreezeReason::None => err!(CreditError::InvalidFreezeReason),
+            reason => Ok(reason as u8),
+        }
+    }
+}
+
+#[repr(u8)]
+pub enum InstallmentPlan {
+    MerchantAbsorbed = 0, // parcelas iguais sem juros; custo descontado do lojista
+    BuyerPaid = 1,        // parcelado com juros (tabela Price) pago pelo comprador
+    Restructure = 2,      // renegociação do saldo em atraso, emitida pelo protocolo
+}
+
+#[repr(u8)]
+pub enum OrderStatus {
+    Open = 0,
+    PartiallyRefunded = 1,
+    Refunded = 2,
+    Disputed = 3,
+}
+
+#[repr(u8)]
+pub enum AutopayMode {
+    Off = 0,
+    Minimum = 1,   // pagamento mínimo da fatura
+    Statement = 2, // total da fatura (vencido + fatura + juros + multas)
+}
+
+#[repr(u8)]
+pub enum DisputeOutcome {
+    BuyerWins = 0,
+    MerchantWins = 1,
+}
+
+// ---------------- Helpers ----------------
+pub fn bps_of(amount: u64, bps: u32) -> u64 {
+    (amount as u128 * bps as u128 / 10_000) as u64
+}
+
+pub struct CollateralValue {
+    pub value_usdc: u64,
+    pub weighted_usdc: u64, // valor × LTV de cada posição
+}
+
+/// Avalia as posições do `owner`: `PositionIndex` seguido de trincas `[Position, PriceAccount, Mint]`.
+/// Exige todas as posições abertas (contagem do índice), para que omitir colateral não derrube o HF.
+/// O preço é em USDC (6 casas) por unidade inteira do token.
+pub fn value_collateral<'info>(
+    owner: &Pubkey,
+    accounts: &'info [AccountInfo<'info>],
+    cfg: &Config,
+    now: i64,
+) -> Result<CollateralValue> {
+    let (position_count, accounts) = split_position_index(owner, accounts)?;
+    let groups = accounts.chunks_exact(3);
+    require!(
+        groups.remainder().is_empty() && groups.len() == position_count,
+        CreditError::InvalidCollateralAccounts
+    );
+    let mut seen: Vec<Pubkey> = Vec::with_capacity(accounts.len() / 3);
+    let mut value_usdc: u128 = 0;
+    let mut weighted_usdc: u128 = 0;
+    for group in groups {
+        let position = Account::<Position>::try_from(&group[0])?;
+        let price = Account::<PriceAccount>::try_from(&group[1])?;
+        let mint = InterfaceAccount::<Mint>::try_from(&group[2])?;
+        require_keys_eq!(
+            position.owner,
+            *owner,
+            CreditError::InvalidCollateralAccounts
+        );
+        require_keys_eq!(
+            position.mint,
+            price.mint,
+            CreditError::InvalidCollateralAccounts
+        );
+        require_keys_eq!(
+            position.mint,
+            mint.key(),
+            CreditError::InvalidCollateralAccounts
+        );
+        require!(
+            !seen.contains(&position.mint),
+            CreditError::DuplicateCollateral
+        );
+        require!(
+            cfg.max_price_age_secs == 0 || now - price.last_ts <= cfg.max_price_age_secs,
+            CreditError::StalePrice
+        );
+        seen.push(position.mint);
+
+        let value =
+            position.amount as u128 * price.price_usdc_6 as u128 / 10u128.pow(mint.decimals as u32);
+        value_usdc += value;
+        weighted_usdc += value * position.ltv_bps as u128 / 10_000;
+    }
+    Ok(CollateralValue {
+        value_usdc: value_usdc.min(u64::MAX as u128) as u64,
+        weighted_usdc: weighted_usdc.min(u64::MAX as u128) as u64,
+    })
+}
+
+/// Separa o PDA `PositionIndex` do owner (primeira conta) e devolve quantas posições ele abriu.
+fn split_position_index<'info>(
+    owner: &Pubkey,
+    accounts: &'info [AccountInfo<'info>],
+) -> Result<(usize, &'info [AccountInfo<'info>])> {
+    let (index, rest) = accounts
+        .split_first()
+        .ok_or(CreditError::InvalidCollateralAccounts)?;
+    let (index_pda, _) =
+        Pubkey::find_program_address(&[b"pos_index", owner.as_ref()], &collateral_vault::ID);
+    require_keys_eq!(
+        index.key(),
+        index_pda,
+        CreditError::InvalidCollateralAccounts
+    );
+    // Índice ainda não criado = owner nunca abriu posição
+    if index.data_is_empty() {
+        return Ok((0, rest));
+    }
+    let count = Account::<PositionIndex>::try_from(index)?.count as usize;
+    Ok((count, rest))
+}
+
+/// Alguma posição do owner é de token pump? Recebe `PositionIndex` + pares
+/// `[Position, PDA PumpToken]`; PumpToken inexistente = token não volátil.
+pub fn has_volatile_collateral<'info>(
+    owner: &Pubkey,
+    accounts: &'info [AccountInfo<'info>],
+) -> Result<bool> {
+    let (position_count, accounts) = split_position_index(owner, accounts)?;
+    require!(
+        accounts.len() == position_count * 2,
+        CreditError::InvalidCollateralAccounts
+    );
+    let mut seen: Vec<Pubkey> = Vec::with_capacity(position_count);
+    let mut volatile = false;
+    for pair in accounts.chunks_exact(2) {
+        let position = Account::<Position>::try_from(&pair[0])?;
+        require_keys_eq!(
+            position.owner,
+            *owner,
+            CreditError::InvalidCollateralAccounts
+        );
+        require!(
+            !seen.contains(&position.mint),
+            CreditError::DuplicateCollateral
+        );
+        seen.push(position.mint);
+        let (pump_token, _) = Pubkey::find_program_address(
+            &[b"pump_token", position.mint.as_ref()],
+            &collateral_vault::ID,
+        );
+        require_keys_eq!(
+            pair[1].key(),
+            pump_token,
+            CreditError::InvalidCollateralAccounts
+        );
+        volatile |= !pair[1].data_is_empty() && *pair[1].owner == collateral_vault::ID;
+    }
+    Ok(volatile)
+}
+
+/// Total depositado em todas as posições do owner (`PositionIndex` + cada `Position`).
+pub fn locked_collateral<'info>(
+    owner: &Pubkey,
+    accounts: &'info [AccountInfo<'info>],
+) -> Result<u64> {
+    let (position_count, accounts) = split_position_index(owner, accounts)?;
+    require!(
+        accounts.len() == position_count,
+        CreditError::InvalidCollateralAccounts
+    );
+    let mut seen: Vec<Pubkey> = Vec::with_capacity(accounts.len());
+    let mut amount: u64 = 0;
+    for ai in accounts {
+        let position = Account::<Position>::try_from(ai)?;
+        require_keys_eq!(
+            position.owner,
+            *owner,
+            CreditError::InvalidCollateralAccounts
+        );
+        require!(
+            !seen.contains(&position.mint),
+            CreditError::DuplicateCollateral
+        );
+        seen.push(position.mint);
+        amount = amount.saturating_add(position.amount);
+    }
+    Ok(amount)
+}
+
+/// `PriceAccount` opcional da moeda da conta, para `Config::settlement_quote`/`settlement_rate`.
+fn price_of<'a>(price: &'a Option<Box<Account<'_, PriceAccount>>>) -> Option<&'a PriceAccount> {
+    price.as_deref().map(|p| &**p)
+}
+
+/// Nota de fato antecipada pelo pool configurado: `advanced` e com o pool como beneficiário.
+fn advanced_by_pool(cfg: &Config, note: &receivables::NoteState) -> bool {
+    note.status == receivables::NOTE_STATUS_ADVANCED && note.beneficiary == cfg.cash_pool
+}
+
+/// Recompra de nota antecipada: `transfer` devolve `net_amount` à token account do
+/// beneficiário (`beneficiary_info`), conferida contra o dono e a moeda da nota.
+fn buy_back_note<'info>(
+    transfer: CpiContext<'_, '_, '_, 'info, spl_token::TransferChecked<'info>>,
+    beneficiary: &Pubkey,
+    beneficiary_info: &'info AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    net_amount: u64,
+) -> Result<()> {
+    let target = InterfaceAccount::<TokenAccount>::try_from(beneficiary_info)?;
+    require!(
+        target.owner == *beneficiary
+            && target.mint == mint.key()
+            && transfer.accounts.to.key() == beneficiary_info.key(),
+        CreditError::InvalidClawbackAccount
+    );
+    spl_token::transfer_checked(transfer, net_amount, mint.decimals)
+}
+
+/// Estorno de compra feita por cartão adicional: devolve ao `spent_amount` do delegate o
+/// principal estornado desde `refunded_before_amount`. Compra do owner ou delegate revogado: nada.
+fn restore_delegate_spend(
+    info: &AccountInfo,
+    order: &OrderRecord,
+    refunded_before_amount: u64,
+) -> Result<()> {
+    if order.authority == order.owner || info.data_is_empty() {
+        return Ok(());
+    }
+    require_keys_eq!(*info.owner, crate::ID, CreditError::Unauthorized);
+    let restored_amount = order.principal_share_amount(order.refunded_amount)
+        - order.principal_share_amount(refunded_before_amount);
+    let mut data = info.try_borrow_mut_data()?;
+    let mut delegate = Delegate::try_deserialize(&mut &data[..])?;
+    delegate.spent_amount = delegate.spent_amount.saturating_sub(restored_amount);
+    delegate.try_serialize(&mut &mut data[..])
+}
+
+/// Carrega a nota `index` do pedido, conferindo o PDA `[b"note", order_id, index]`.
+fn load_order_note<'info>(
+    order_id: &[u8; 32],
+    index: u8,
+    info: &'info AccountInfo<'info>,
+) -> Result<Account<'info, receivables::NoteState>> {
+    let (expected, _) =
+        Pubkey::find_program_address(&[b"note", order_id.as_ref(), &[index]], &receivables::ID);
+    require_keys_eq!(info.key(), expected, CreditError::NoteAccountsMismatch);
+    Account::try_from(info)
+}
+
+pub fn score_band(score: u16) -> u8 {
+    SCORE_BAND_THRESHOLDS
+        .iter()
+        .rposition(|&min| score >= min)
+        .unwrap_or(0) as u8
+}
+
+/// HF em bps = colateral ponderado ÷ saldo devedor (sem dívida, HF máximo).
+pub fn health_factor_bps(weighted_collateral_usdc: u64, owed_usdc: u64) -> u32 {
+    if owed_usdc == 0 {
+        return u32::MAX;
+    }
+    (weighted_collateral_usdc as u128 * 10_000 / owed_usdc as u128).min(u32::MAX as u128) as u32
+}
+
+/// Divide o valor em parcelas iguais, sem juros; o resto da divisão vai para a primeira.
+pub fn split_installments(
+    amount: u64,
+    installments: u8,
+) -> [InstallmentSplit; MAX_INSTALLMENTS] {
+    let mut parts = [InstallmentSplit::default(); MAX_INSTALLMENTS];
+    let n = installments as u64;
+    for (i, part) in parts.iter_mut().take(installments as usize).enumerate() {
+        part.principal_amount = amount / n;
+        if i == 0 {
+            part.principal_amount += amount % n;
+        }
+    }
+    parts
+}
+
+/// Tabela Price (sistema francês): prestação fixa PMT = P·r·(1+r)^n / ((1+r)^n − 1), com juros
+/// de cada mês sobre o saldo devedor. Ponto fixo em `RATE_SCALE`; a última parcela amortiza o
+/// saldo restante para absorver arredondamentos. `None` em overflow.
+pub fn price_table_schedule(
+    principal_amount: u64,
+    rate_bps_monthly: u32,
+    installments: u8,
+) -> Option<[InstallmentSplit; MAX_INSTALLMENTS]> {
+    if rate_bps_monthly == 0 {
+        return Some(split_installments(principal_amount, installments));
+    }
+    let n = installments as usize;
+    let r = rate_bps_monthly as u128 * RATE_SCALE / 10_000;
+    let mut growth = RATE_SCALE; // (1+r)^n
+    for _ in 0..n {
+        growth = growth.checked_mul(RATE_SCALE + r)? / RATE_SCALE;
+    }
+    let pmt = (principal_amount as u128)
+        .checked_mul(r)?
+        .checked_mul(growth)?
+        .checked_div((growth - RATE_SCALE).checked_mul(RATE_SCALE)?)?;
+
+    let mut parts = [InstallmentSplit::default(); MAX_INSTALLMENTS];
+    let mut balance = principal_amount as u128;
+    for (i, part) in parts.iter_mut().take(n).enumerate() {
+        let interest = balance * r / RATE_SCALE;
+        let principal = if i + 1 == n {
+            balance
+        } else {
+            pmt.saturating_sub(interest).min(balance)
+        };
+        balance -= principal;
+        part.principal_amount = u64::try_from(principal).ok()?;
+        part.interest_amount = u64::try_from(interest).ok()?;
+    }
+    Some(parts)
+}
+
+/// Valor presente de `amount` recebido daqui a `periods` meses à taxa mensal dada.
+pub fn present_value(amount: u64, rate_bps_monthly: u32, periods: u32) -> Option<u64> {
+    let r = rate_bps_monthly as u128 * RATE_SCALE / 10_000;
+    let mut value = amount as u128;
+    for _ in 0..periods {
+        value = value.checked_mul(RATE_SCALE)? / (RATE_SCALE + r);
+    }
+    u64::try_from(value).ok()
+}
+
+/// Pagamento mínimo: `min_payment_bps` do total, com piso `min_payment_floor_usdc` (nunca acima do total).
+/// `floor_amount`: `min_payment_floor_usdc` já convertido para a moeda da conta.
+pub fn min_payment(cfg: &Config, total_due_amount: u64, floor_amount: u64) -> u64 {
+    bps_of(total_due_amount, cfg.min_payment_bps)
+        .max(floor_amount)
+        .min(total_due_amount)
+}
+
+/// Próximo timestamp (00:00 UTC) estritamente após `after_ts` cujo dia do mês é `day`.
+/// Meses mais curtos usam o último dia disponível.
+pub fn next_billing_ts(after_ts: i64, day: u8) -> i64 {
+    let (mut y, mut m, _) = civil_from_days(after_ts.div_euclid(SECONDS_PER_DAY));
+    loop {
+        let d = (day.max(1) as u32).min(days_in_month(y, m));
+        let ts = days_from_civil(y, m, d) * SECONDS_PER_DAY;
+        if ts > after_ts {
+            return ts;
+        }
+        if m == 12 {
+            y += 1;
+            m = 1;
+        } else {
+            m += 1;
+        }
+    }
+}
+
+fn days_in_month(y: i64, m: u32) -> u32 {
+    match m {
+        2 if (y % 4 == 0 && y % 100 != 0) || y % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Algoritmos de calendário civil (H. Hinnant), dias contados a partir de 1970-01-01
+fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = if m > 2 { m - 3 } else { m + 9 } as i64;
+    let doy = (153 * mp + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Config zerada (como recém-alocada); os testes ajustam só o que usam.
+    fn blank_config() -> Config {
+        Config::deserialize(&mut &vec![0u8; std::mem::size_of::<Config>()][..]).unwrap()
+    }
+
+    fn blank_account() -> CreditAccount {
+        CreditAccount::deserialize(&mut &vec![0u8; std::mem::size_of::<CreditAccount>()][..])
+            .unwrap()
+    }
+
+    /// Conta com fatura de 100 USDC vencida em `due` e mínimo de 10 em aberto.
+    fn billed_account(due: i64) -> CreditAccount {
+        let mut acct = blank_account();
+        acct.used_amount = 100_000_000;
+        acct.statement_balance_amount = 100_000_000;
+        acct.min_payment_due_amount = 10_000_000;
+        acct.due_date_ts = due;
+        acct.last_accrual_ts = due - 10 * SECONDS_PER_DAY;
+        acct
+    }
+
+    fn accrual_config() -> Config {
+        let mut cfg = blank_config();
+        cfg.penalty_rate_bps_daily = 15;
+        cfg.late_fee_bps = 200;
+        cfg
+    }
+
+    fn date_ts(y: i64, m: u32, d: u32) -> i64 {
+        days_from_civil(y, m, d) * SECONDS_PER_DAY
+    }
+
+    #[test]
+    fn min_payment_uses_bps_floor_and_total() {
+        let mut cfg = blank_config();
+        cfg.min_payment_bps = 1_000;
+        assert_eq!(min_payment(&cfg, 300_000_000, 10_000_000), 30_000_000);
+        // Abaixo do piso vale o piso...
+        assert_eq!(min_payment(&cfg, 50_000_000, 10_000_000), 10_000_000);
+        // ...mas nunca acima do total da fatura
+        assert_eq!(min_payment(&cfg, 8_000_000, 10_000_000), 8_000_000);
+        assert_eq!(min_payment(&cfg, 0, 10_000_000), 0);
+    }
+
+    #[test]
+    fn civil_calendar_round_trips() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2024, 1, 1), 19_723);
+        assert_eq!(civil_from_days(19_723 + 59), (2024, 2, 29));
+        for z in -800_000..800_000 {
+            let (y, m, d) = civil_from_days(z);
+            assert_eq!(days_from_civil(y, m, d), z);
+        }
+    }
+
+    #[test]
+    fn next_billing_ts_is_strictly_after_and_clamps_short_months() {
+        assert_eq!(next_billing_ts(date_ts(2024, 1, 15), 31), date_ts(2024, 1, 31));
+        // Exatamente no dia de corte passa para o mês seguinte; fevereiro bissexto
+        assert_eq!(next_billing_ts(date_ts(2024, 1, 31), 31), date_ts(2024, 2, 29));
+        assert_eq!(next_billing_ts(date_ts(2023, 2, 1), 30), date_ts(2023, 2, 28));
+        assert_eq!(next_billing_ts(date_ts(2023, 12, 20) + 3_600, 5), date_ts(2024, 1, 5));
+        // Dia 0 é tratado como dia 1
+        assert_eq!(next_billing_ts(date_ts(2024, 3, 10), 0), date_ts(2024, 4, 1));
+    }
+
+    #[test]
+    fn accrue_charges_fee_once_and_daily_interest_after_due() {
+        let cfg = accrual_config();
+        let due = date_ts(2024, 3, 5);
+        let mut acct = billed_account(due);
+
+        // Antes do vencimento nada corre
+        acct.accrue(&cfg, due - 3_600);
+        assert_eq!((acct.late_fees_amount, acct.accrued_interest_amount), (0, 0));
+
+        acct.accrue(&cfg, due + 2 * SECONDS_PER_DAY + 3_600);
+        assert_eq!(acct.late_fees_amount, 2_000_000);
+        assert_eq!(acct.accrued_interest_amount, 300_000);
+        // A fração de dia fica para a próxima apuração
+        assert_eq!(acct.last_accrual_ts, due + 2 * SECONDS_PER_DAY);
+
+        acct.accrue(&cfg, due + 3 * SECONDS_PER_DAY);
+        assert_eq!(acct.late_fees_amount, 2_000_000);
+        assert_eq!(acct.accrued_interest_amount, 450_000);
+    }
+
+    #[test]
+    fn accrue_skips_fee_when_minimum_was_paid() {
+        let cfg = accrual_config();
+        let due = date_ts(2024, 3, 5);
+        let mut acct = billed_account(due);
+        acct.min_payment_due_amount = 0;
+
+        acct.accrue(&cfg, due + SECONDS_PER_DAY);
+        assert_eq!(acct.late_fees_amount, 0);
+        assert!(!acct.late_fee_assessed);
+        assert_eq!(acct.accrued_interest_amount, 150_000);
+    }
+
+    #[test]
+    fn late_fee_at_due_date_is_left_to_statement_close() {
+        let cfg = accrual_config();
+        let due = date_ts(2024, 3, 5);
+        let mut acct = billed_account(due);
+
+        // Pagar no próprio vencimento ainda é pontual
+        acct.accrue(&cfg, due);
+        assert_eq!(acct.late_fees_amount, 0);
+        // O corte no vencimento apura a multa antes de rolar a fatura
+        assert_eq!(acct.assess_late_fee(&cfg), 2_000_000);
+        assert_eq!(acct.assess_late_fee(&cfg), 0);
+    }
+
+    #[test]
+    fn health_factor_is_weighted_collateral_over_debt() {
+        assert_eq!(health_factor_bps(500_000_000, 400_000_000), 12_500);
+        assert_eq!(health_factor_bps(0, 1), 0);
+        assert_eq!(health_factor_bps(1, 0), u32::MAX);
+        assert_eq!(health_factor_bps(u64::MAX, 1), u32::MAX);
+    }
+
+    #[test]
+    fn projected_health_factor_counts_the_new_debt() {
+        let usdc = SettlementQuote::parity(6);
+        let mut acct = blank_account();
+        // Sem colateral, qualquer compra derruba o HF a zero
+        assert_eq!(acct.projected_health_factor_bps(&usdc, 1), 0);
+        assert_eq!(acct.projected_health_factor_bps(&usdc, 0), u32::MAX);
+
+        acct.weighted_collateral_usdc = 500_000_000;
+        acct.used_amount = 100_000_000;
+        assert_eq!(acct.projected_health_factor_bps(&usdc, 300_000_000), 12_500);
+        assert_eq!(acct.projected_health_factor_bps(&usdc, 400_000_000), 10_000);
+    }
+
+    #[test]
+    fn split_installments_puts_the_remainder_on_the_first() {
+        let parts = split_installments(100_000_001, 3);
+        assert_eq!(parts[0].principal_amount, 33_333_335);
+        assert_eq!(parts[1].principal_amount, 33_333_333);
+        assert_eq!(parts[2].principal_amount, 33_333_333);
+        assert!(parts.iter().all(|p| p.interest_amount == 0));
+        assert!(parts[3..].iter().all(|p| p.total_amount() == 0));
+
+        let full = split_installments(12_000_000, MAX_INSTALLMENTS as u8);
+        assert!(full.iter().all(|p| p.principal_amount == 1_000_000));
+    }
+
+    #[test]
+    fn score_rewards_on_time_history_and_low_utilization() {
+        let now = date_ts(2025, 1, 1);
+        let mut acct = blank_account();
+        acct.opened_ts = now;
+        acct.limit_amount = 1_000_000_000;
+        acct.update_score(now);
+        // Sem histórico: 200 + 250 sem atraso + 200 sem uso + 0 de idade
+        assert_eq!(acct.score, 650);
+
+        acct.on_time_payments = 3;
+        acct.used_amount = 500_000_000;
+        acct.update_score(now + 365 * SECONDS_PER_DAY);
+        assert_eq!(acct.score, 400 + 250 + 100 + 75);
+
+        acct.late_payments = 1;
+        acct.restructured_ts = now;
+        acct.update_score(now + 365 * SECONDS_PER_DAY - 1);
+        assert_eq!(acct.score, 300 + 250 + 100 + 74 - 150);
+    }
+
+    #[test]
+    fn payment_waterfall_pays_the_oldest_past_due_first() {
+        let mut acct = blank_account();
+        acct.cycle_count = 5;
+        acct.late_fees_amount = 1;
+        acct.accrued_interest_amount = 2;
+        acct.cash_balance_amount = 3;
+        acct.past_due_cycles_amount = [10, 0, 20, 0, 0, 0];
+        acct.statement_balance_amount = 40;
+        acct.schedule_amount[0] = 50;
+        acct.used_amount = 123;
+
+        let first = acct.apply_payment(28);
+        assert_eq!((first.to_late_fees_amount, first.to_interest_amount), (1, 2));
+        assert_eq!(first.to_past_due_amount, 25);
+        // Ciclo 2 (mais antigo) quitado antes do ciclo 4; saque ainda intocado
+        assert_eq!(acct.past_due_cycles_amount, [5, 0, 0, 0, 0, 0]);
+        assert_eq!(first.to_cash_amount, 0);
+
+        let second = acct.apply_payment(60);
+        assert_eq!(second.to_past_due_amount, 5);
+        assert_eq!(second.to_statement_amount, 40);
+        // Saque em dinheiro só depois da fatura corrente, antes das parcelas futuras
+        assert_eq!(second.to_cash_amount, 3);
+        assert_eq!(acct.cash_repaid_amount, 3);
+        assert_eq!(second.to_future_amount, 12);
+        assert_eq!(acct.used_amount, 123 - 25 - 60);
+    }
+
+    #[test]
+    fn past_due_slots_follow_the_billing_cycle() {
+        let mut acct = blank_account();
+        acct.cycle_count = 10;
+        assert_eq!(acct.past_due_slot(9), 0);
+        assert_eq!(acct.past_due_slot(7), 2);
+        // Ciclos além do histórico caem no último saldo
+        assert_eq!(acct.past_due_slot(0), PAST_DUE_CYCLES - 1);
+
+        acct.past_due_cycles_amount = [0, 0, 30, 0, 0, 0];
+        acct.used_amount = 30;
+        // Estorno do ciclo 7 sai do saldo dele; o que sobra já tinha sido pago
+        assert_eq!(acct.reverse_installment(7, 40), 10);
+        assert_eq!(acct.past_due_amount(), 0);
+        assert_eq!(acct.used_amount, 0);
+    }
+
+    #[test]
+    fn price_table_has_fixed_payments_and_amortizes_the_principal() {
+        let parts = price_table_schedule(1_000_000_000, 300, 3).unwrap();
+        let principal: u64 = parts.iter().map(|p| p.principal_amount).sum();
+        assert_eq!(principal, 1_000_000_000);
+        // Juros do primeiro mês sobre o saldo cheio
+        assert_eq!(parts[0].interest_amount, 30_000_000);
+        // PMT = 353,530363 USDC; a última parcela absorve o arredondamento
+        for part in &parts[..3] {
+            assert!(part.total_amount().abs_diff(353_530_363) <= 2, "{}", part.total_amount());
+        }
+        assert!(parts[0].interest_amount > parts[1].interest_amount);
+        assert!(parts[1].interest_amount > parts[2].interest_amount);
+        assert!(parts[3..].iter().all(|p| p.total_amount() == 0));
+    }
+
+    #[test]
+    fn price_table_without_rate_is_the_equal_split() {
+        let parts = price_table_schedule(100_000_001, 0, 3).unwrap();
+        let split = split_installments(100_000_001, 3);
+        assert!(parts.iter().zip(split.iter()).all(|(a, b)| {
+            a.principal_amount == b.principal_amount && a.interest_amount == b.interest_amount
+        }));
+    }
+
+    #[test]
+    fn settlement_quote_comes_from_the_price_account() {
+        let (usdc, usdt) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut cfg = blank_config();
+        cfg.usdc_mint = usdc;
+        cfg.max_price_age_secs = 3_600;
+        cfg.settlement_mints[0] = SettlementMint::usdc(usdc, 6);
+        cfg.settlement_mints[1] = SettlementMint {
+            mint: usdt,
+            decimals: 6,
+            enabled: true,
+        };
+        cfg.settlement_mint_count = 2;
+        let price = PriceAccount {
+            mint: usdt,
+            price_usdc_6: 999_000,
+            last_ts: 1_000,
+            bump: 0,
+        };
+
+        // USDC em paridade, sem conta de preço e sem envelhecer
+        let quote = cfg.settlement_rate(&usdc, None, i64::MAX).unwrap();
+        assert_eq!(quote.to_usdc(5_000_000), 5_000_000);
+        assert!(cfg.settlement_quote(&usdt, None).is_err());
+        assert_eq!(
+            cfg.settlement_quote(&usdt, Some(&price)).unwrap().to_usdc(1_000_000),
+            999_000
+        );
+        // Risco exige cotação recente; a conversão do piso aceita a última conhecida
+        assert!(cfg.settlement_rate(&usdt, Some(&price), 4_600).is_ok());
+        assert!(cfg.settlement_rate(&usdt, Some(&price), 4_601).is_err());
+        let other = PriceAccount {
+            mint: Pubkey::new_unique(),
+            ..price
+        };
+        assert!(cfg.settlement_quote(&usdt, Some(&other)).is_err());
+    }
+
+    #[test]
+    fn refunded_principal_is_proportional_to_the_face_value() {
+        let mut order =
+            OrderRecord::deserialize(&mut &vec![0u8; std::mem::size_of::<OrderRecord>()][..])
+                .unwrap();
+        assert_eq!(order.principal_share_amount(50_000_000), 0);
+        order.amount = 300_000_000;
+        order.interest_amount = 60_000_000;
+        assert_eq!(order.principal_share_amount(120_000_000), 100_000_000);
+        // Estornos parciais somam o mesmo principal que o estorno total
+        let first = order.principal_share_amount(120_000_000);
+        let rest = order.principal_share_amount(360_000_000) - first;
+        assert_eq!(first + rest, 300_000_000);
+        assert_eq!(order.principal_share_amount(u64::MAX), 300_000_000);
+    }
+
+    #[test]
+    fn present_value_discounts_monthly() {
+        assert_eq!(present_value(100_000_000, 0, 12), Some(100_000_000));
+        assert_eq!(present_value(102_000_000, 200, 1), Some(100_000_000));
+        // 100 / 1,02^12 = 78,849317
+        let pv = present_value(100_000_000, 200, 12).unwrap();
+        assert!(pv.abs_diff(78_849_317) <= 12, "{pv}");
+        assert!(present_value(100_000_000, 200, 3) > present_value(100_000_000, 200, 4));
+    }
 }
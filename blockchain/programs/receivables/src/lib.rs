@@ -2,54 +2,158 @@ use anchor_lang::prelude::*;
 
 declare_id!("89YkmHwfwtzEAoARKQ3m3QhLaYuJiBrKzYZrbZ6B8DGc"); 
 
+/// Programa credit_line: só o PDA `credit_vault` dele emite notas (via CPI no `charge`).
+pub const CREDIT_LINE_PROGRAM_ID: Pubkey = pubkey!("2DgViSNpi9CLMHjLmzqJGHvTGMjZXo97pMufmXJuqAQs");
+
+/// Programa advance_pool: só o PDA de um pool dele marca a nota como antecipada.
+pub const ADVANCE_POOL_PROGRAM_ID: Pubkey = pubkey!("8zKbc5hProPy7xB2M5iDKABCLhcb68ezdyAixiC7NcDe");
+
 #[program]
 pub mod receivables {
     use super::*;
 
-    /// Emite UMA nota (parcela). Você chamará N vezes para N parcelas.
+    /// Emite UMA nota (parcela). O credit_line chama N vezes, via CPI, para N parcelas.
     pub fn mint_note(
         ctx: Context<MintNote>,
         order_id: [u8; 32],
         index: u8,
-        buyer: Pubkey,
-        merchant: Pubkey,
-        amount_usdc: u64,
-        due_ts: i64,
+        params: NoteParams,
     ) -> Result<()> {
         // Capture a key antes do borrow mutável para evitar E0502
         let note_key = ctx.accounts.note_state.key();
 
         let state = &mut ctx.accounts.note_state;
         state.note_id = note_key;
-        state.merchant = merchant;
-        state.beneficiary = merchant; // por padrão, o lojista é o beneficiário
-        state.buyer = buyer;
-        state.amount_usdc = amount_usdc;
-        state.due_ts = due_ts;
+        state.merchant = params.merchant;
+        state.beneficiary = params.merchant; // por padrão, o lojista é o beneficiário
+        state.buyer = params.buyer;
+        state.amount = params.amount;
+        state.net_amount = params.net_amount;
+        state.plan = params.plan;
+        state.due_ts = params.due_ts;
         state.status = 0; // issued
+        state.disputed = false;
         state.order_id = order_id;
+        state.mint = params.mint;
         state.bump = ctx.bumps.note_state;
 
         emit!(NoteIssued {
             note: note_key,
-            buyer,
-            merchant,
-            amount_usdc,
-            due_ts,
+            buyer: params.buyer,
+            merchant: params.merchant,
+            amount: params.amount,
+            net_amount: params.net_amount,
+            due_ts: params.due_ts,
             index,
         });
 
         Ok(())
     }
 
+    /// Reduz (estorno parcial) ou cancela (estorno total) uma nota. Só o credit_line emite/estorna.
+    /// O líquido ao lojista cai na mesma proporção do valor de face.
+    pub fn reduce_note(ctx: Context<ReduceNote>, amount: u64) -> Result<()> {
+        let note_key = ctx.accounts.note_state.key();
+        let state = &mut ctx.accounts.note_state;
+        require!(amount > 0, NoteError::InvalidAmount);
+        require!(amount <= state.amount, NoteError::ExceedsNoteAmount);
+
+        let net_cut = net_reduction(state.net_amount, state.amount, amount);
+        state.amount -= amount;
+        state.net_amount -= net_cut;
+        if state.amount == 0 {
+            state.status = NOTE_STATUS_CANCELLED;
+        }
+
+        emit!(NoteReduced {
+            note: note_key,
+            amount,
+            net_amount: net_cut,
+            remaining_amount: state.amount,
+        });
+        Ok(())
+    }
+
+    /// Marca/desmarca a nota como contestada pelo comprador (disputa no credit_line).
+    /// Nota contestada não pode ser antecipada.
+    pub fn set_note_disputed(ctx: Context<SetNoteDisputed>, disputed: bool) -> Result<()> {
+        let note_key = ctx.accounts.note_state.key();
+        ctx.accounts.note_state.disputed = disputed;
+        emit!(NoteDisputeChanged {
+            note: note_key,
+            disputed,
+        });
+        Ok(())
+    }
+
+    /// Baixa da conta do comprador como perda: a nota ainda não paga passa a `defaulted`.
+    /// Só o credit_line marca (via CPI no `force_close_account`).
+    pub fn mark_defaulted(ctx: Context<MarkDefaulted>) -> Result<()> {
+        let note_key = ctx.accounts.note_state.key();
+        let state = &mut ctx.accounts.note_state;
+        require!(state.status != NOTE_STATUS_CANCELLED, NoteError::NoteCancelled);
+        state.status = NOTE_STATUS_DEFAULTED;
+        emit!(NoteDefaulted {
+            note: note_key,
+            amount: state.amount,
+        });
+        Ok(())
+    }
+
     // stubs para futuras alterações de estado
     pub fn mark_paid(_ctx: Context<MarkPaid>) -> Result<()> {
         Ok(())
     }
 
-    pub fn assign_beneficiary(_ctx: Context<AssignBeneficiary>, _new_beneficiary: Pubkey) -> Result<()> {
+    /// Cessão da nota: só o beneficiário atual transfere o direito de receber (ex.: lojista
+    /// vendendo ao pool na antecipação). Nota contestada ou cancelada não é cedida.
+    pub fn assign_beneficiary(ctx: Context<AssignBeneficiary>, new_beneficiary: Pubkey) -> Result<()> {
+        let note_key = ctx.accounts.note_state.key();
+        let state = &mut ctx.accounts.note_state;
+        require!(!state.disputed, NoteError::NoteDisputed);
+        require!(state.status != NOTE_STATUS_CANCELLED, NoteError::NoteCancelled);
+        let old_beneficiary = state.beneficiary;
+        state.beneficiary = new_beneficiary;
+        emit!(NoteAssigned {
+            note: note_key,
+            old_beneficiary,
+            new_beneficiary,
+        });
         Ok(())
     }
+
+    /// Antecipação: o lojista cede a nota ao pool, que assina com o seu PDA (via CPI no
+    /// `advance_pool::advance`). A nota passa a `advanced`, com o pool como beneficiário.
+    pub fn advance_note(ctx: Context<AdvanceNote>) -> Result<()> {
+        let note_key = ctx.accounts.note_state.key();
+        let pool = ctx.accounts.pool.key();
+        let state = &mut ctx.accounts.note_state;
+        require!(!state.disputed, NoteError::NoteDisputed);
+        require!(state.status == NOTE_STATUS_ISSUED, NoteError::NoteNotIssued);
+        let old_beneficiary = state.beneficiary;
+        state.beneficiary = pool;
+        state.status = NOTE_STATUS_ADVANCED;
+        emit!(NoteAssigned {
+            note: note_key,
+            old_beneficiary,
+            new_beneficiary: pool,
+        });
+        Ok(())
+    }
+}
+
+pub const NOTE_STATUS_ISSUED: u8 = 0;
+pub const NOTE_STATUS_ADVANCED: u8 = 1;
+pub const NOTE_STATUS_PAST_DUE: u8 = 5;
+pub const NOTE_STATUS_DEFAULTED: u8 = 6;
+pub const NOTE_STATUS_CANCELLED: u8 = 8;
+
+/// Parcela do líquido correspondente a `cut` do valor de face (arredonda para baixo).
+pub fn net_reduction(net_amount: u64, amount: u64, cut: u64) -> u64 {
+    if cut >= amount {
+        return net_amount;
+    }
+    ((net_amount as u128) * (cut as u128) / (amount as u128)) as u64
 }
 
 #[account]
@@ -58,16 +162,34 @@ pub struct NoteState {
     pub merchant: Pubkey,
     pub beneficiary: Pubkey,
     pub buyer: Pubkey,
-    pub amount_usdc: u64,
+    pub amount: u64,
+    pub net_amount: u64,       // valor líquido ao lojista; base do desconto na antecipação
+    pub plan: u8,            // 0=lojista absorve, 1=comprador paga juros
     pub due_ts: i64,
-    pub status: u8,          // 0=issued,1=advanced,2=due_upcoming,3=due_today,4=paid,5=past_due,6=defaulted,7=settled
+    pub status: u8,          // 0=issued,1=advanced,2=due_upcoming,3=due_today,4=paid,5=past_due,6=defaulted,7=settled,8=cancelled
+    pub disputed: bool,      // em disputa no credit_line
     pub order_id: [u8; 32],  // **32 bytes** padronizado
+    pub mint: Pubkey,        // stablecoin da nota (moeda da conta do comprador)
     pub bump: u8,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct NoteParams {
+    pub buyer: Pubkey,
+    pub merchant: Pubkey,
+    pub amount: u64,
+    pub due_ts: i64,
+    pub net_amount: u64, // valor líquido ao lojista
+    pub plan: u8,
+    pub mint: Pubkey,  // stablecoin em que a nota é paga
+}
+
 #[derive(Accounts)]
 #[instruction(order_id: [u8; 32], index: u8)]
 pub struct MintNote<'info> {
+    #[account(seeds = [b"credit_vault"], bump, seeds::program = CREDIT_LINE_PROGRAM_ID)]
+    pub issuer: Signer<'info>,
+
     #[account(mut)]
     pub payer: Signer<'info>,
 
@@ -84,6 +206,30 @@ pub struct MintNote<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct ReduceNote<'info> {
+    #[account(seeds = [b"credit_vault"], bump, seeds::program = CREDIT_LINE_PROGRAM_ID)]
+    pub issuer: Signer<'info>,
+    #[account(mut)]
+    pub note_state: Account<'info, NoteState>,
+}
+
+#[derive(Accounts)]
+pub struct SetNoteDisputed<'info> {
+    #[account(seeds = [b"credit_vault"], bump, seeds::program = CREDIT_LINE_PROGRAM_ID)]
+    pub issuer: Signer<'info>,
+    #[account(mut)]
+    pub note_state: Account<'info, NoteState>,
+}
+
+#[derive(Accounts)]
+pub struct MarkDefaulted<'info> {
+    #[account(seeds = [b"credit_vault"], bump, seeds::program = CREDIT_LINE_PROGRAM_ID)]
+    pub issuer: Signer<'info>,
+    #[account(mut)]
+    pub note_state: Account<'info, NoteState>,
+}
+
 #[derive(Accounts)]
 pub struct MarkPaid<'info> {
     #[account(mut)]
@@ -94,9 +240,23 @@ pub struct MarkPaid<'info> {
 
 #[derive(Accounts)]
 pub struct AssignBeneficiary<'info> {
-    #[account(mut)]
-    pub admin_or_owner: Signer<'info>,
-    #[account(mut)]
+    pub beneficiary: Signer<'info>,
+    #[account(mut, has_one = beneficiary @ NoteError::NotBeneficiary)]
+    pub note_state: Account<'info, NoteState>,
+}
+
+#[derive(Accounts)]
+pub struct AdvanceNote<'info> {
+    pub beneficiary: Signer<'info>,
+    /// CHECK: admin do pool, só compõe as seeds do PDA do pool
+    pub pool_admin: UncheckedAccount<'info>,
+    #[account(
+        seeds = [b"pool", pool_admin.key().as_ref()],
+        bump,
+        seeds::program = ADVANCE_POOL_PROGRAM_ID
+    )]
+    pub pool: Signer<'info>,
+    #[account(mut, has_one = beneficiary @ NoteError::NotBeneficiary)]
     pub note_state: Account<'info, NoteState>,
 }
 
@@ -105,7 +265,51 @@ pub struct NoteIssued {
     pub note: Pubkey,
     pub buyer: Pubkey,
     pub merchant: Pubkey,
-    pub amount_usdc: u64,
+    pub amount: u64,
+    pub net_amount: u64,
     pub due_ts: i64,
     pub index: u8,
 }
+
+#[event]
+pub struct NoteReduced {
+    pub note: Pubkey,
+    pub amount: u64,
+    pub net_amount: u64,
+    pub remaining_amount: u64,
+}
+
+#[event]
+pub struct NoteDisputeChanged {
+    pub note: Pubkey,
+    pub disputed: bool,
+}
+
+#[event]
+pub struct NoteDefaulted {
+    pub note: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct NoteAssigned {
+    pub note: Pubkey,
+    pub old_beneficiary: Pubkey,
+    pub new_beneficiary: Pubkey,
+}
+
+#[error_code]
+pub enum NoteError {
+    #[msg("Invalid amount")]
+    InvalidAmount,
+    #[msg("Reduction exceeds note amount")]
+    ExceedsNoteAmount,
+    #[msg("Only the current beneficiary can assign the note")]
+    NotBeneficiary,
+    #[msg("Note is under dispute")]
+    NoteDisputed,
+    #[msg("Note is cancelled")]
+    NoteCancelled,
+    #[msg("Note is not in the issued state")]
+    NoteNotIssued,
+}
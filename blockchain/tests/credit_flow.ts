@@ -1,11 +1,68 @@
 import * as anchor from "@coral-xyz/anchor";
-import { Program } from "@coral-xyz/anchor";
+import { BN, Program } from "@coral-xyz/anchor";
+import {
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  AccountLayout,
+  MINT_SIZE,
+  TOKEN_PROGRAM_ID,
+  createAssociatedTokenAccountIdempotentInstruction,
+  createInitializeMint2Instruction,
+  createMintToInstruction,
+  getAssociatedTokenAddressSync,
+} from "@solana/spl-token";
+import { BankrunProvider, startAnchor } from "anchor-bankrun";
 import { expect } from "chai";
+import { Clock, ProgramTestContext } from "solana-bankrun";
+
+const { ComputeBudgetProgram, Keypair, LAMPORTS_PER_SOL, PublicKey, SystemProgram, Transaction } =
+  anchor.web3;
+type Keypair = anchor.web3.Keypair;
+type PublicKey = anchor.web3.PublicKey;
+
+const USDC = 1_000_000; // 6 casas decimais (todas as moedas do teste)
+const DAY = 86_400;
+const PLAN_MERCHANT_ABSORBED = 0;
+const PLAN_BUYER_PAID = 1;
+
+const seed = (s: string) => Buffer.from(s);
+const pda = (seeds: Buffer[], programId: PublicKey) =>
+  PublicKey.findProgramAddressSync(seeds, programId)[0];
+const writable = (pubkey: PublicKey) => ({ pubkey, isSigner: false, isWritable: true });
+const readonly = (pubkey: PublicKey) => ({ pubkey, isSigner: false, isWritable: false });
+
+function orderId(label: string): number[] {
+  const id = Buffer.alloc(32);
+  id.write(label);
+  return Array.from(id);
+}
+
+// Bankrun recusa transações idênticas no mesmo blockhash; o preço de CU as diferencia
+let nonceCounter = 0;
+const nonce = () => ComputeBudgetProgram.setComputeUnitPrice({ microLamports: ++nonceCounter });
+
+/** Falha com o erro `name` do programa (ou da Anchor, ex.: `ConstraintSeeds`). */
+async function expectError(promise: Promise<unknown>, program: Program, name: string) {
+  const entry = program.idl.errors?.find((e) => e.name.toLowerCase() === name.toLowerCase());
+  const code = entry?.code ?? (anchor.LangErrorCode as Record<string, number>)[name];
+  expect(code, `código do erro ${name}`).to.not.equal(undefined);
+  try {
+    await promise;
+  } catch (err: any) {
+    const got = err?.error?.errorCode?.number ?? err?.code;
+    const text = `${err}`;
+    expect(
+      got === code || text.includes(`0x${code.toString(16)}`) || text.includes(name),
+      `esperado ${name}, veio: ${text}`
+    ).to.equal(true);
+    return;
+  }
+  expect.fail(`esperado ${name}, mas a transação passou`);
+}
 
 describe("cronia flow", () => {
-  const provider = anchor.AnchorProvider.local();
-  anchor.setProvider(provider);
-  const wallet = provider.wallet as anchor.Wallet;
+  let context: ProgramTestContext;
+  let provider: BankrunProvider;
+  let admin: Keypair;
 
   // Program handles
   let credit: Program;
@@ -13,9 +70,357 @@ describe("cronia flow", () => {
   let recv: Program;
   let pool: Program;
 
+  let usdcMint: PublicKey;
+  let configPda: PublicKey;
+  let vaultAuthority: PublicKey; // PDA `credit_vault`: cofre do protocolo e emissor das notas
+  let usdcVault: PublicKey;
+  let poolPda: PublicKey;
+  let poolVault: PublicKey;
+  let merchant: Keypair;
+  let merchantAta: PublicKey;
+  let vaultConfigPda: PublicKey;
+  let backing: PublicKey; // colateral comum das contas que só precisam de HF para comprar
+
+  const creditPda = (owner: PublicKey) => pda([seed("credit"), owner.toBuffer()], credit.programId);
+  const orderPda = (id: number[]) => pda([seed("order"), Buffer.from(id)], credit.programId);
+  const delegatePda = (owner: PublicKey, key: PublicKey) =>
+    pda([seed("delegate"), creditPda(owner).toBuffer(), key.toBuffer()], credit.programId);
+  const notePda = (id: number[], index: number) =>
+    pda([seed("note"), Buffer.from(id), Buffer.from([index])], recv.programId);
+  const positionIndexPda = (owner: PublicKey) =>
+    pda([seed("pos_index"), owner.toBuffer()], vault.programId);
+  const positionPda = (owner: PublicKey, mint: PublicKey) =>
+    pda([seed("pos"), owner.toBuffer(), mint.toBuffer()], vault.programId);
+  const pricePda = (mint: PublicKey) => pda([seed("price"), mint.toBuffer()], vault.programId);
+  const collateralVaultPda = (mint: PublicKey) =>
+    pda([seed("vault"), mint.toBuffer()], vault.programId);
+  const ata = (mint: PublicKey, owner: PublicKey) =>
+    getAssociatedTokenAddressSync(mint, owner, true);
+
+  const creditAccount = (owner: PublicKey): Promise<any> =>
+    credit.account.creditAccount.fetch(creditPda(owner));
+  const orderRecord = (id: number[]): Promise<any> => credit.account.orderRecord.fetch(orderPda(id));
+  const note = (id: number[], index: number): Promise<any> =>
+    recv.account.noteState.fetch(notePda(id, index));
+
+  async function send(ixs: anchor.web3.TransactionInstruction[], signers: Keypair[] = []) {
+    await provider.sendAndConfirm(new Transaction().add(nonce(), ...ixs), signers);
+  }
+
+  async function now(): Promise<number> {
+    return Number((await context.banksClient.getClock()).unixTimestamp);
+  }
+
+  async function warpTo(unixTs: number) {
+    const clock = await context.banksClient.getClock();
+    const slot = clock.slot + BigInt(1);
+    context.warpToSlot(slot);
+    context.setClock(
+      new Clock(slot, clock.epochStartTimestamp, clock.epoch, clock.leaderScheduleEpoch, BigInt(unixTs))
+    );
+  }
+
+  async function newUser(): Promise<Keypair> {
+    const kp = Keypair.generate();
+    await send([
+      SystemProgram.transfer({
+        fromPubkey: admin.publicKey,
+        toPubkey: kp.publicKey,
+        lamports: 10 * LAMPORTS_PER_SOL,
+      }),
+    ]);
+    return kp;
+  }
+
+  async function createMint(): Promise<PublicKey> {
+    const mint = Keypair.generate();
+    await send(
+      [
+        SystemProgram.createAccount({
+          fromPubkey: admin.publicKey,
+          newAccountPubkey: mint.publicKey,
+          space: MINT_SIZE,
+          lamports: LAMPORTS_PER_SOL / 10,
+          programId: TOKEN_PROGRAM_ID,
+        }),
+        createInitializeMint2Instruction(mint.publicKey, 6, admin.publicKey, null),
+      ],
+      [mint]
+    );
+    return mint.publicKey;
+  }
+
+  /** Cria (se preciso) o ATA de `owner` e credita `amount` unidades inteiras do token. */
+  async function mintTokens(mint: PublicKey, owner: PublicKey, amount: number): Promise<PublicKey> {
+    const account = ata(mint, owner);
+    const ixs = [createAssociatedTokenAccountIdempotentInstruction(admin.publicKey, account, owner, mint)];
+    if (amount > 0) {
+      ixs.push(createMintToInstruction(mint, account, admin.publicKey, amount * USDC));
+    }
+    await send(ixs);
+    return account;
+  }
+
+  async function balance(account: PublicKey): Promise<number> {
+    const info = await context.banksClient.getAccount(account);
+    return info ? Number(AccountLayout.decode(info.data).amount) : 0;
+  }
+
+  function configParams() {
+    return {
+      minHfBpsForNewCharges: 12_000,
+      minHfBpsForWithdraw: 13_000,
+      penaltyRateBpsDaily: 15,
+      lateFeeBps: 200,
+      graceVolatileDays: 15,
+      graceAnyDays: 30,
+      defaultAfterDays: 60,
+      minPaymentBps: 1_000,
+      minPaymentFloorUsdc: new BN(10 * USDC),
+      maxPriceAgeSecs: new BN(3_600),
+      liquidationBonusBps: 500,
+      closeFactorBps: 5_000,
+      configDelaySecs: new BN(DAY),
+      riskAuthority: PublicKey.default,
+      scoreTierCapsUsdc: [new BN(0), new BN(0), new BN(0), new BN(0)],
+      installmentRateBpsMonthly: 300,
+      merchantDiscountRateBpsMonthly: 200,
+      disputeWindowDays: 90,
+      disputeResolutionDays: 30,
+      cashPool: poolPda,
+      cashRateBpsDaily: 10,
+      minHfBpsForCash: 15_000,
+    };
+  }
+
+  async function openAccount(owner: Keypair, limit = 1_000, mint = usdcMint) {
+    await credit.methods
+      .openAccount()
+      .accountsPartial({
+        owner: owner.publicKey,
+        creditAccount: creditPda(owner.publicKey),
+        config: configPda,
+        mint,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([owner])
+      .rpc();
+    await credit.methods
+      .setLimit(new BN(limit * USDC))
+      .accountsPartial({
+        admin: admin.publicKey,
+        config: configPda,
+        creditAccount: creditPda(owner.publicKey),
+        owner: owner.publicKey,
+      })
+      .rpc();
+  }
+
+  /** `remaining_accounts` da avaliação: PositionIndex + trincas [Position, PriceAccount, Mint]. */
+  function collateralAccounts(owner: PublicKey, mints: PublicKey[] = []) {
+    return [
+      readonly(positionIndexPda(owner)),
+      ...mints.flatMap((m) => [readonly(positionPda(owner, m)), readonly(pricePda(m)), readonly(m)]),
+    ];
+  }
+
+  /** Cotação da moeda da conta no collateral_vault; `null` para contas em USDC (paridade). */
+  async function settlementPrice(owner: PublicKey): Promise<PublicKey | null> {
+    const { mint } = await creditAccount(owner);
+    return mint.equals(usdcMint) ? null : pricePda(mint);
+  }
+
+  async function refreshHealth(owner: PublicKey, mints: PublicKey[] = []) {
+    await credit.methods
+      .refreshHealth()
+      .accountsPartial({
+        creditAccount: creditPda(owner),
+        owner,
+        config: configPda,
+        settlementPrice: await settlementPrice(owner),
+      })
+      .remainingAccounts(collateralAccounts(owner, mints))
+      .preInstructions([nonce()])
+      .rpc();
+  }
+
+  /** PDA da fatura de índice `cycleIndex` (1 = primeira fatura) da abertura atual da conta. */
+  const statementPda = (acct: { owner: PublicKey; openedTs: BN }, cycleIndex: number) => {
+    const index = Buffer.alloc(4);
+    index.writeUInt32LE(cycleIndex);
+    const opened = acct.openedTs.toArrayLike(Buffer, "le", 8);
+    return pda([seed("statement"), acct.owner.toBuffer(), opened, index], credit.programId);
+  };
+
+  /** Avança o relógio até o fechamento (se preciso) e fecha a fatura do ciclo. */
+  async function closeStatement(owner: PublicKey) {
+    const acct = await creditAccount(owner);
+    await warpTo(Math.max(await now(), acct.nextStatementTs.toNumber()));
+    await credit.methods
+      .statementClose()
+      .accountsPartial({
+        payer: admin.publicKey,
+        creditAccount: creditPda(owner),
+        owner,
+        config: configPda,
+        settlementPrice: await settlementPrice(owner),
+        statement: statementPda(acct, acct.cycleCount + 1),
+        systemProgram: SystemProgram.programId,
+      })
+      .preInstructions([nonce()])
+      .rpc();
+  }
+
+  async function charge(
+    owner: Keypair,
+    id: number[],
+    amount: number,
+    installments: number,
+    plan = PLAN_MERCHANT_ABSORBED,
+    authority = owner
+  ) {
+    return credit.methods
+      .charge(new BN(amount * USDC), installments, id, plan)
+      .accountsPartial({
+        authority: authority.publicKey,
+        merchant: merchant.publicKey,
+        creditAccount: creditPda(owner.publicKey),
+        owner: owner.publicKey,
+        delegate: authority === owner ? null : delegatePda(owner.publicKey, authority.publicKey),
+        spendControls: null,
+        merchantProfile: null,
+        config: configPda,
+        settlementPrice: await settlementPrice(owner.publicKey),
+        orderRecord: orderPda(id),
+        vaultAuthority,
+        receivablesProgram: recv.programId,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts(Array.from({ length: installments }, (_, i) => writable(notePda(id, i))))
+      .signers([authority, merchant])
+      .rpc();
+  }
+
+  async function repay(owner: Keypair, amount: number, mint = usdcMint) {
+    await credit.methods
+      .repay(new BN(amount * USDC))
+      .accountsPartial({
+        payer: owner.publicKey,
+        creditAccount: creditPda(owner.publicKey),
+        owner: owner.publicKey,
+        config: configPda,
+        mint,
+        payerAta: ata(mint, owner.publicKey),
+        vaultAuthority,
+        vault: ata(mint, vaultAuthority),
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([owner])
+      .rpc();
+  }
+
+  /** Antecipa a nota ao pool: o pool paga o líquido ao lojista, que assina a cessão. */
+  function advance(id: number[], index: number, signer: Keypair = admin) {
+    return pool.methods
+      .advance()
+      .accountsPartial({
+        pool: poolPda,
+        admin: signer.publicKey,
+        merchant: merchant.publicKey,
+        vault: poolVault,
+        mint: usdcMint,
+        merchantAta,
+        noteState: notePda(id, index),
+        receivablesProgram: recv.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([signer, merchant])
+      .preInstructions([nonce()])
+      .rpc();
+  }
+
+  async function setPrice(mint: PublicKey, priceUsdc: number) {
+    await vault.methods
+      .setPrice(new BN(Math.round(priceUsdc * USDC)))
+      .accountsPartial({
+        priceAccount: pricePda(mint),
+        mint,
+        vaultConfig: vaultConfigPda,
+        authority: admin.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .preInstructions([nonce()])
+      .rpc();
+  }
+
+  /** Token de colateral com cofre no collateral_vault e preço publicado. */
+  async function createCollateral(priceUsdc: number): Promise<PublicKey> {
+    const mint = await createMint();
+    await vault.methods
+      .initVaultForMint()
+      .accountsPartial({
+        vault: collateralVaultPda(mint),
+        mint,
+        admin: admin.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+    await mintTokens(mint, collateralVaultPda(mint), 0);
+    await setPrice(mint, priceUsdc);
+    return mint;
+  }
+
+  /** Abre a posição do owner, define o LTV e deposita `amount` tokens. */
+  async function depositCollateral(owner: Keypair, mint: PublicKey, amount: number, ltvBps: number) {
+    const position = positionPda(owner.publicKey, mint);
+    await vault.methods
+      .openPositionPump()
+      .accountsPartial({
+        position,
+        positionIndex: positionIndexPda(owner.publicKey),
+        owner: owner.publicKey,
+        mint,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([owner])
+      .rpc();
+    await vault.methods
+      .setPositionLtv(ltvBps)
+      .accountsPartial({ vaultConfig: vaultConfigPda, position, admin: admin.publicKey })
+      .rpc();
+    const ownerAta = await mintTokens(mint, owner.publicKey, amount);
+    await vault.methods
+      .depositPump(new BN(amount * USDC))
+      .accountsPartial({
+        vaultConfig: vaultConfigPda,
+        vault: collateralVaultPda(mint),
+        mint,
+        position,
+        ownerAta,
+        vaultAta: ata(mint, collateralVaultPda(mint)),
+        owner: owner.publicKey,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([owner])
+      .rpc();
+  }
+
+  /** Deposita 1000 tokens de `backing` (US$ 1, LTV 50%: 500 USDC ponderados) e avalia o HF. */
+  async function backWithCollateral(owner: Keypair) {
+    await depositCollateral(owner, backing, 1_000, 5_000);
+    await setPrice(backing, 1);
+    await refreshHealth(owner.publicKey, [backing]);
+  }
+
   before(async () => {
-    // Após anchor build, os IDLs ficam em target/idl
-    // Anchor auto-carrega por nome (CamelCase) se o tsconfig/scritps estiverem ok
+    // Os programas vêm de target/deploy (anchor build); o relógio é controlado pelo teste
+    context = await startAnchor(".", [], []);
+    provider = new BankrunProvider(context);
+    anchor.setProvider(provider);
+    admin = context.payer;
+
     // @ts-ignore
     credit = anchor.workspace.CreditLine as Program;
     // @ts-ignore
@@ -24,111 +429,1666 @@ describe("cronia flow", () => {
     recv = anchor.workspace.Receivables as Program;
     // @ts-ignore
     pool = anchor.workspace.AdvancePool as Program;
-  });
-
-  it("init configs + open credit account + set limit + charge", async () => {
-    const admin = wallet.payer;
-
-    // --- init credit config
-    const [configPda] = anchor.web3.PublicKey.findProgramAddressSync(
-      [Buffer.from("credit_config")],
-      credit.programId
-    );
-
-    await credit.methods.initConfig({
-      minHfBpsForNewCharges: 12000,
-      minHfBpsForWithdraw: 13000,
-      penaltyRateBpsDaily: 15,
-      lateFeeBps: 200,
-      graceVolatileDays: 15,
-      graceAnyDays: 30
-    }).accounts({
-      admin: wallet.publicKey,
-      config: configPda,
-      systemProgram: anchor.web3.SystemProgram.programId
-    }).rpc();
-
-    // --- open credit account
-    const [creditAcctPda] = anchor.web3.PublicKey.findProgramAddressSync(
-      [Buffer.from("credit"), wallet.publicKey.toBuffer()],
-      credit.programId
-    );
 
-    await credit.methods.openAccount().accounts({
-      owner: wallet.publicKey,
-      creditAccount: creditAcctPda,
-      systemProgram: anchor.web3.SystemProgram.programId
-    }).rpc();
+    usdcMint = await createMint();
+    configPda = pda([seed("credit_config")], credit.programId);
+    vaultAuthority = pda([seed("credit_vault")], credit.programId);
+    usdcVault = ata(usdcMint, vaultAuthority);
+    poolPda = pda([seed("pool"), admin.publicKey.toBuffer()], pool.programId);
 
-    // --- set limit
-    await credit.methods.setLimit(new anchor.BN(1_000_000)) // 1,000,000 micro-USDC = 1,000 USDC
-      .accounts({
-        admin: wallet.publicKey,
+    // --- init credit config (cria também o cofre USDC do protocolo)
+    await credit.methods
+      .initConfig(configParams())
+      .accountsPartial({
+        admin: admin.publicKey,
         config: configPda,
-        creditAccount: creditAcctPda,
-        owner: wallet.publicKey
+        usdcMint,
+        vaultAuthority,
+        usdcVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
       })
       .rpc();
 
-    // --- charge (3x)
-    const orderId = Buffer.alloc(32);
-    orderId.write("order-001");
-    await credit.methods.charge(new anchor.BN(300_000), 3, Array.from(orderId))
-      .accounts({
-        creditAccount: creditAcctPda,
-        config: configPda
+    // --- pool de antecipação com cofre USDC do PDA do pool
+    await pool.methods
+      .initPool()
+      .accountsPartial({ admin: admin.publicKey, pool: poolPda, systemProgram: SystemProgram.programId })
+      .rpc();
+    // Caixa do pool para antecipações
+    poolVault = await mintTokens(usdcMint, poolPda, 1_000);
+    await pool.methods
+      .setVault()
+      .accountsPartial({ admin: admin.publicKey, pool: poolPda, vault: poolVault })
+      .rpc();
+
+    merchant = await newUser();
+    merchantAta = await mintTokens(usdcMint, merchant.publicKey, 0);
+
+    // --- collateral_vault: saques e liquidações só com a assinatura do PDA do credit_line
+    vaultConfigPda = pda([seed("vault_cfg")], vault.programId);
+    await vault.methods
+      .initVaultConfig()
+      .accountsPartial({
+        vaultConfig: vaultConfigPda,
+        admin: admin.publicKey,
+        systemProgram: SystemProgram.programId,
       })
       .rpc();
+    await vault.methods
+      .setLiquidationAuthority(vaultAuthority)
+      .accountsPartial({ vaultConfig: vaultConfigPda, admin: admin.publicKey })
+      .rpc();
+    backing = await createCollateral(1);
   });
 
-  it("mint one receivable note and advance to pool", async () => {
-    const payer = wallet.publicKey;
-    const merchant = wallet.publicKey; // usando o próprio wallet como merchant para teste
-    const buyer = wallet.publicKey;
+  describe("charge, notes and repay", () => {
+    let buyer: Keypair;
+    const firstOrder = orderId("order-001");
 
-    const orderId = Buffer.alloc(32);
-    orderId.write("order-001");
-    const index = 0;
-    const dueTs = Math.floor(Date.now()/1000) + 86400; // +1 dia
+    it("open credit account + set limit + charge in installments", async () => {
+      buyer = await newUser();
+      await openAccount(buyer);
+      // Compra exige HF recente, já contando a própria compra
+      await backWithCollateral(buyer);
+      await charge(buyer, firstOrder, 300, 3);
 
-    const [notePda] = anchor.web3.PublicKey.findProgramAddressSync(
-      [Buffer.from("note"), orderId, Buffer.from([index])],
-      recv.programId
-    );
+      const acct = await creditAccount(buyer.publicKey);
+      expect(acct.usedAmount.toNumber()).eq(300 * USDC);
+      const order = await orderRecord(firstOrder);
+      expect(order.installments).eq(3);
+      expect(order.merchant.toBase58()).eq(merchant.publicKey.toBase58());
+      // Uma nota por parcela, emitida pelo credit_line via CPI
+      for (let i = 0; i < 3; i++) {
+        const n = await note(firstOrder, i);
+        expect(n.buyer.toBase58()).eq(buyer.publicKey.toBase58());
+        expect(n.beneficiary.toBase58()).eq(merchant.publicKey.toBase58());
+        expect(n.amount.toNumber()).eq(100 * USDC);
+      }
+    });
 
-    await recv.methods.mintNote(
-      new anchor.BN(100_000), // 100 USDC
-      new anchor.BN(dueTs),
-      Array.from(orderId),
-      index
-    ).accounts({
-      payer,
-      merchant,
-      buyer,
-      noteState: notePda,
-      systemProgram: anchor.web3.SystemProgram.programId
-    }).rpc();
-
-    // init pool
-    const [poolPda] = anchor.web3.PublicKey.findProgramAddressSync(
-      [Buffer.from("pool"), payer.toBuffer()],
-      pool.programId
-    );
-    await pool.methods.initPool().accounts({
-      admin: payer,
-      pool: poolPda,
-      systemProgram: anchor.web3.SystemProgram.programId
-    }).rpc();
-
-    // advance (cede nota ao pool)
-    await pool.methods.advance().accounts({
-      pool: poolPda,
-      admin: payer,
-      noteState: notePda
-    }).rpc();
-
-    // pegar a conta da nota e checar beneficiary == pool
-    const noteAfter = await recv.account.noteState.fetch(notePda);
-    expect((noteAfter as any).beneficiary.toBase58()).eq(poolPda.toBase58());
+    it("rejects a charge signed by someone other than the owner", async () => {
+      const stranger = await newUser();
+      await expectError(
+        charge(buyer, orderId("order-stranger"), 10, 1, PLAN_MERCHANT_ABSORBED, stranger),
+        credit,
+        "Unauthorized"
+      );
+    });
+
+    it("rejects a charge against another owner's account", async () => {
+      const stranger = await newUser();
+      const id = orderId("order-hijack");
+      await expectError(
+        credit.methods
+          .charge(new BN(10 * USDC), 1, id, PLAN_MERCHANT_ABSORBED)
+          .accountsPartial({
+            authority: stranger.publicKey,
+            merchant: merchant.publicKey,
+            creditAccount: creditPda(buyer.publicKey),
+            owner: stranger.publicKey,
+            delegate: null,
+            spendControls: null,
+            merchantProfile: null,
+            config: configPda,
+            settlementPrice: null,
+            orderRecord: orderPda(id),
+            vaultAuthority,
+            receivablesProgram: recv.programId,
+            systemProgram: SystemProgram.programId,
+          })
+          .remainingAccounts([writable(notePda(id, 0))])
+          .signers([stranger, merchant])
+          .rpc(),
+        credit,
+        "ConstraintSeeds"
+      );
+    });
+
+    it("rejects a charge above the available limit", async () => {
+      await expectError(charge(buyer, orderId("order-002"), 800, 1), credit, "InsufficientLimit");
+    });
+
+    it("rejects replaying an order id", async () => {
+      // O OrderRecord do pedido já existe: o `init` falha no System Program
+      try {
+        await charge(buyer, firstOrder, 10, 1);
+      } catch (err) {
+        expect(`${err}`).to.match(/already in use|custom program error: 0x0/);
+        return;
+      }
+      expect.fail("o pedido repetido foi aceito");
+    });
+
+    it("rejects a charge that would push the HF below the minimum", async () => {
+      // 500 ponderados / (300 + 150) => HF 1.11 < 1.20
+      await expectError(charge(buyer, orderId("order-003"), 150, 1), credit, "HfTooLow");
+    });
+
+    it("rejects a charge with no collateral behind it", async () => {
+      const bare = await newUser();
+      await openAccount(bare);
+      await refreshHealth(bare.publicKey);
+      await expectError(charge(bare, orderId("order-bare"), 10, 1), credit, "HfTooLow");
+    });
+
+    it("repays in USDC into the protocol vault", async () => {
+      await mintTokens(usdcMint, buyer.publicKey, 100);
+      const vaultBefore = await balance(usdcVault);
+      await repay(buyer, 100);
+
+      expect(await balance(usdcVault)).eq(vaultBefore + 100 * USDC);
+      expect((await creditAccount(buyer.publicKey)).usedAmount.toNumber()).eq(200 * USDC);
+    });
+
+    it("rejects notes minted outside credit_line", async () => {
+      const id = orderId("order-rogue");
+      const params = {
+        buyer: buyer.publicKey,
+        merchant: merchant.publicKey,
+        amount: new BN(100 * USDC),
+        dueTs: new BN((await now()) + 30 * DAY),
+        netAmount: new BN(100 * USDC),
+        plan: PLAN_MERCHANT_ABSORBED,
+        mint: usdcMint,
+      };
+      await expectError(
+        recv.methods
+          .mintNote(id, 0, params)
+          .accountsPartial({
+            issuer: admin.publicKey,
+            payer: admin.publicKey,
+            noteState: notePda(id, 0),
+            systemProgram: SystemProgram.programId,
+          })
+          .rpc(),
+        recv,
+        "ConstraintSeeds"
+      );
+    });
+
+    it("only lets the pool admin advance", async () => {
+      const stranger = await newUser();
+      await expectError(advance(firstOrder, 0, stranger), pool, "ConstraintSeeds");
+    });
+
+    it("advances a note to the pool paying the merchant the net", async () => {
+      const net = (await note(firstOrder, 0)).netAmount.toNumber();
+      const merchantBefore = await balance(merchantAta);
+      const poolBefore = await balance(poolVault);
+      await advance(firstOrder, 0);
+
+      const n = await note(firstOrder, 0);
+      expect(n.beneficiary.toBase58()).eq(poolPda.toBase58());
+      expect(n.status).eq(1); // advanced
+      expect(await balance(merchantAta)).eq(merchantBefore + net);
+      expect(await balance(poolVault)).eq(poolBefore - net);
+      // Já cedida: não se antecipa duas vezes
+      await expectError(advance(firstOrder, 0), pool, "NoteNotAdvanceable");
+    });
+  });
+
+  describe("statements", () => {
+    let owner: Keypair;
+
+    before(async () => {
+      owner = await newUser();
+      await openAccount(owner);
+      await backWithCollateral(owner);
+      await charge(owner, orderId("stmt-001"), 300, 3);
+    });
+
+    it("bills the first installment with minimum payment and due date", async () => {
+      const before = await creditAccount(owner.publicKey);
+      const closeTs = before.nextStatementTs.toNumber();
+      await closeStatement(owner.publicKey);
+
+      const st = await credit.account.statement.fetch(statementPda(before, 1));
+      expect(st.cycleIndex).eq(1);
+      expect(st.totalDueAmount.toNumber()).eq(100 * USDC);
+      // 10% de 100 = 10, igual ao piso de 10 USDC
+      expect(st.minPaymentAmount.toNumber()).eq(10 * USDC);
+      // Vencimento no mesmo dia de corte do mês seguinte
+      const dueTs = st.dueDateTs.toNumber();
+      expect(dueTs).gt(closeTs);
+      expect(new Date(dueTs * 1000).getUTCDate()).eq(before.billingCycleDay);
+      expect(dueTs - closeTs).within(28 * DAY, 31 * DAY);
+
+      const acct = await creditAccount(owner.publicKey);
+      expect(acct.cycleCount).eq(1);
+      expect(acct.statementBalanceAmount.toNumber()).eq(100 * USDC);
+      expect(acct.minPaymentDueAmount.toNumber()).eq(10 * USDC);
+      expect(acct.nextStatementTs.toNumber()).eq(dueTs);
+    });
+
+    it("rejects closing again before the next cycle", async () => {
+      await expectError(
+        credit.methods
+          .statementClose()
+          .accountsPartial({
+            payer: admin.publicKey,
+            creditAccount: creditPda(owner.publicKey),
+            owner: owner.publicKey,
+            config: configPda,
+            settlementPrice: null,
+            statement: statementPda(await creditAccount(owner.publicKey), 2),
+            systemProgram: SystemProgram.programId,
+          })
+          .rpc(),
+        credit,
+        "StatementNotDue"
+      );
+    });
+
+    it("carries the unpaid minimum into the next statement", async () => {
+      await closeStatement(owner.publicKey);
+      // Corte no vencimento com o mínimo em aberto: multa de 2% sobre a fatura de 100
+      expect((await creditAccount(owner.publicKey)).lateFeesAmount.toNumber()).eq(2 * USDC);
+
+      const st = await credit.account.statement.fetch(
+        statementPda(await creditAccount(owner.publicKey), 2)
+      );
+      expect(st.cycleIndex).eq(2);
+      // Segunda parcela + saldo não pago da fatura anterior
+      expect(st.totalDueAmount.toNumber()).gte(200 * USDC);
+      expect(st.minPaymentAmount.toNumber()).gte(30 * USDC);
+    });
+  });
+
+  describe("installment plans", () => {
+    let owner: Keypair;
+
+    before(async () => {
+      owner = await newUser();
+      await openAccount(owner);
+      await backWithCollateral(owner);
+    });
+
+    it("charges buyer-paid installments on the Price table", async () => {
+      const id = orderId("price-001");
+      await charge(owner, id, 300, 3, PLAN_BUYER_PAID);
+
+      const order = await orderRecord(id);
+      const interest = order.interestAmount.toNumber();
+      // 3% a.m. sobre 300 em 3x: PMT ~106,06, juros totais ~18,18
+      expect(interest).within(18_170_000, 18_190_000);
+      expect((await creditAccount(owner.publicKey)).usedAmount.toNumber()).eq(300 * USDC + interest);
+
+      const notes = await Promise.all([0, 1, 2].map((i) => note(id, i)));
+      const totals = notes.map((n) => n.amount.toNumber());
+      expect(totals.reduce((a, b) => a + b, 0)).eq(300 * USDC + interest);
+      for (const t of totals) expect(Math.abs(t - totals[0])).lte(2);
+    });
+
+    it("pays the merchant only the principal on buyer-paid plans", async () => {
+      const id = orderId("price-001");
+      const order = await orderRecord(id);
+      expect(order.merchantNetAmount.toNumber()).eq(300 * USDC);
+      const nets = await Promise.all([0, 1, 2].map(async (i) => (await note(id, i)).netAmount.toNumber()));
+      expect(nets.reduce((a, b) => a + b, 0)).eq(300 * USDC);
+    });
+
+    it("discounts merchant-absorbed notes to present value", async () => {
+      const id = orderId("absorbed-001");
+      await charge(owner, id, 90, 3, PLAN_MERCHANT_ABSORBED);
+
+      const order = await orderRecord(id);
+      expect(order.interestAmount.toNumber()).eq(0);
+      // 2% a.m.: 30/1,02 + 30/1,02^2 + 30/1,02^3 ~ 86,52
+      expect(order.merchantNetAmount.toNumber()).within(86_510_000, 86_520_000);
+      const nets = await Promise.all([0, 1, 2].map(async (i) => (await note(id, i)).netAmount.toNumber()));
+      // Parcelas mais distantes valem menos hoje
+      expect(nets[0]).gt(nets[1]);
+      expect(nets[1]).gt(nets[2]);
+      expect((await note(id, 0)).amount.toNumber()).eq(30 * USDC);
+    });
+
+    it("rejects an unknown plan", async () => {
+      await expectError(charge(owner, orderId("plan-bad"), 10, 1, 7), credit, "InvalidPlan");
+    });
+  });
+
+  describe("liquidate", () => {
+    let owner: Keypair;
+    let liquidator: Keypair;
+    let collateral: PublicKey;
+
+    function liquidate(amount: number) {
+      return credit.methods
+        .liquidate(new BN(amount * USDC))
+        .accountsPartial({
+          liquidator: liquidator.publicKey,
+          creditAccount: creditPda(owner.publicKey),
+          owner: owner.publicKey,
+          config: configPda,
+          settlementPrice: null,
+          mint: usdcMint,
+          liquidatorAta: ata(usdcMint, liquidator.publicKey),
+          vaultAuthority,
+          vault: usdcVault,
+          vaultConfig: vaultConfigPda,
+          collateralVault: collateralVaultPda(collateral),
+          collateralMint: collateral,
+          position: positionPda(owner.publicKey, collateral),
+          priceAccount: pricePda(collateral),
+          vaultCollateralAta: ata(collateral, collateralVaultPda(collateral)),
+          liquidatorCollateralAta: ata(collateral, liquidator.publicKey),
+          collateralVaultProgram: vault.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(collateralAccounts(owner.publicKey, [collateral]))
+        .signers([liquidator])
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    before(async () => {
+      owner = await newUser();
+      liquidator = await newUser();
+      collateral = await createCollateral(1);
+      await openAccount(owner);
+      // 1000 tokens a US$ 1 com LTV 50%: 500 USDC de colateral ponderado
+      await depositCollateral(owner, collateral, 1_000, 5_000);
+      await refreshHealth(owner.publicKey, [collateral]);
+      await charge(owner, orderId("liq-001"), 400, 1);
+      await mintTokens(usdcMint, liquidator.publicKey, 200);
+      await mintTokens(collateral, liquidator.publicKey, 0);
+    });
+
+    it("rejects liquidating a healthy account", async () => {
+      // 500 / 400 => HF 1.25
+      await expectError(liquidate(100), credit, "NotLiquidatable");
+    });
+
+    it("repays debt and seizes collateral plus bonus below HF 1.0", async () => {
+      // Preço cai pela metade: 250 / 400 => HF 0.625
+      await setPrice(collateral, 0.5);
+      await liquidate(100);
+
+      // 100 USDC + 5% de bônus a US$ 0,50 = 210 tokens
+      expect(await balance(ata(collateral, liquidator.publicKey))).eq(210 * USDC);
+      expect(await balance(ata(usdcMint, liquidator.publicKey))).eq(100 * USDC);
+      expect((await creditAccount(owner.publicKey)).usedAmount.toNumber()).eq(300 * USDC);
+      const position: any = await vault.account.position.fetch(positionPda(owner.publicKey, collateral));
+      expect(position.amount.toNumber()).eq(790 * USDC);
+    });
+
+    it("rejects repaying above the close factor", async () => {
+      // Close factor de 50% sobre 300 USDC
+      await expectError(liquidate(160), credit, "CloseFactorExceeded");
+    });
+  });
+
+  describe("refresh_limit", () => {
+    let owner: Keypair;
+    let collateral: PublicKey;
+
+    function refreshLimit() {
+      return credit.methods
+        .refreshLimit()
+        .accountsPartial({
+          creditAccount: creditPda(owner.publicKey),
+          owner: owner.publicKey,
+          config: configPda,
+          settlementPrice: null,
+        })
+        .remainingAccounts(collateralAccounts(owner.publicKey, [collateral]))
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    before(async () => {
+      owner = await newUser();
+      collateral = await createCollateral(1);
+      await openAccount(owner);
+      await depositCollateral(owner, collateral, 1_000, 5_000);
+    });
+
+    it("rejects accounts with a manual limit", async () => {
+      await expectError(refreshLimit(), credit, "AutoLimitDisabled");
+    });
+
+    it("derives the limit from collateral, capped by the account ceiling", async () => {
+      await credit.methods
+        .setLimitMode(true, new BN(800 * USDC))
+        .accountsPartial({
+          admin: admin.publicKey,
+          config: configPda,
+          creditAccount: creditPda(owner.publicKey),
+          owner: owner.publicKey,
+        })
+        .rpc();
+
+      // 1000 tokens a US$ 1 com LTV 50%
+      await refreshLimit();
+      expect((await creditAccount(owner.publicKey)).limitAmount.toNumber()).eq(500 * USDC);
+
+      // A US$ 2 o colateral vale 1000 USDC, acima do teto de 800
+      await setPrice(collateral, 2);
+      await refreshLimit();
+      expect((await creditAccount(owner.publicKey)).limitAmount.toNumber()).eq(800 * USDC);
+    });
+  });
+
+  describe("refund", () => {
+    let owner: Keypair;
+    const id = orderId("refund-001");
+    const byCard = orderId("refund-002");
+
+    // Notas da última para a primeira parcela, cada uma com a token account do beneficiário
+    function refund(
+      amount: number,
+      beneficiaries = [merchantAta, merchantAta, merchantAta],
+      order = id,
+      authority = owner.publicKey
+    ) {
+      return credit.methods
+        .refund(new BN(amount * USDC))
+        .accountsPartial({
+          merchant: merchant.publicKey,
+          orderRecord: orderPda(order),
+          creditAccount: creditPda(owner.publicKey),
+          delegate: delegatePda(owner.publicKey, authority),
+          config: configPda,
+          mint: usdcMint,
+          merchantAta,
+          vaultAuthority,
+          receivablesProgram: recv.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(
+          [2, 1, 0].flatMap((i) => [writable(notePda(order, i)), writable(beneficiaries[2 - i])])
+        )
+        .signers([merchant])
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    before(async () => {
+      owner = await newUser();
+      await openAccount(owner);
+      await backWithCollateral(owner);
+      await charge(owner, id, 300, 3);
+    });
+
+    it("partially refunds from the last installment backwards", async () => {
+      await refund(150);
+
+      const last = await note(id, 2);
+      expect(last.amount.toNumber()).eq(0);
+      expect(last.status).eq(8); // cancelada
+      expect((await note(id, 1)).amount.toNumber()).eq(50 * USDC);
+      expect((await note(id, 0)).amount.toNumber()).eq(100 * USDC);
+
+      const order = await orderRecord(id);
+      expect(order.refundedAmount.toNumber()).eq(150 * USDC);
+      expect(order.status).eq(1); // PartiallyRefunded
+      expect((await creditAccount(owner.publicKey)).usedAmount.toNumber()).eq(150 * USDC);
+    });
+
+    it("rejects refunding more than what is left of the order", async () => {
+      await expectError(refund(200), credit, "RefundExceedsOrder");
+    });
+
+    it("claws back from the merchant what the pool paid for an advanced note", async () => {
+      await advance(id, 0);
+      const net = (await note(id, 0)).netAmount.toNumber();
+      const poolBefore = await balance(poolVault);
+      const merchantBefore = await balance(merchantAta);
+
+      await refund(150, [merchantAta, merchantAta, poolVault]);
+
+      expect((await note(id, 0)).status).eq(8);
+      expect(await balance(poolVault)).eq(poolBefore + net);
+      expect(await balance(merchantAta)).eq(merchantBefore - net);
+      expect((await orderRecord(id)).status).eq(2); // Refunded
+    });
+
+    it("gives the refunded principal back to the card that made the purchase", async () => {
+      const card = Keypair.generate();
+      await credit.methods
+        .addDelegate(card.publicKey, {
+          spendLimitAmount: new BN(300 * USDC),
+          perTxMaxAmount: new BN(0),
+          expiresTs: new BN(0),
+          allowedMerchants: [],
+        })
+        .accountsPartial({
+          owner: owner.publicKey,
+          creditAccount: creditPda(owner.publicKey),
+          delegate: delegatePda(owner.publicKey, card.publicKey),
+        })
+        .signers([owner])
+        .rpc();
+      await charge(owner, byCard, 90, 3, PLAN_MERCHANT_ABSORBED, card);
+      const delegate = () =>
+        credit.account.delegate.fetch(delegatePda(owner.publicKey, card.publicKey));
+      expect((await delegate()).spentAmount.toNumber()).eq(90 * USDC);
+
+      await refund(30, undefined, byCard, card.publicKey);
+      expect((await delegate()).spentAmount.toNumber()).eq(60 * USDC);
+      await refund(60, undefined, byCard, card.publicKey);
+      expect((await delegate()).spentAmount.toNumber()).eq(0);
+    });
+  });
+
+  describe("dispute", () => {
+    let owner: Keypair;
+    const advanced = orderId("dispute-001"); // parcela 0 antecipada ao pool
+    const kept = orderId("dispute-002");
+    const BUYER_WINS = 0;
+    const MERCHANT_WINS = 1;
+
+    function openDispute(id: number[], installments: number) {
+      return credit.methods
+        .openDispute()
+        .accountsPartial({
+          owner: owner.publicKey,
+          creditAccount: creditPda(owner.publicKey),
+          orderRecord: orderPda(id),
+          config: configPda,
+          settlementPrice: null,
+          vaultAuthority,
+          receivablesProgram: recv.programId,
+        })
+        .remainingAccounts(Array.from({ length: installments }, (_, i) => writable(notePda(id, i))))
+        .signers([owner])
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    /** `beneficiaries`: token account do beneficiário de cada parcela, na ordem dos índices. */
+    function resolveDispute(
+      id: number[],
+      outcome: number,
+      beneficiaries: PublicKey[],
+      resolver: Keypair = admin
+    ) {
+      return credit.methods
+        .resolveDispute(outcome)
+        .accountsPartial({
+          resolver: resolver.publicKey,
+          orderRecord: orderPda(id),
+          creditAccount: creditPda(owner.publicKey),
+          delegate: delegatePda(owner.publicKey, owner.publicKey),
+          config: configPda,
+          settlementPrice: null,
+          vaultAuthority,
+          mint: usdcMint,
+          vault: usdcVault,
+          receivablesProgram: recv.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(
+          beneficiaries.flatMap((b, i) => [writable(notePda(id, i)), writable(b)])
+        )
+        .signers([resolver])
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    function repayMerchantDebt(id: number[], amount: number) {
+      return credit.methods
+        .repayMerchantDebt(new BN(amount))
+        .accountsPartial({
+          merchant: merchant.publicKey,
+          orderRecord: orderPda(id),
+          config: configPda,
+          mint: usdcMint,
+          merchantAta,
+          vaultAuthority,
+          vault: usdcVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([merchant])
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    before(async () => {
+      owner = await newUser();
+      await openAccount(owner);
+      await backWithCollateral(owner);
+      await charge(owner, advanced, 200, 2);
+      await charge(owner, kept, 100, 1);
+      await advance(advanced, 0);
+      // Cofre do protocolo precisa de saldo para recomprar a nota antecipada
+      await mintTokens(usdcMint, vaultAuthority, 200);
+    });
+
+    it("opens a dispute and flags the notes", async () => {
+      await openDispute(advanced, 2);
+
+      const order = await orderRecord(advanced);
+      expect(order.status).eq(3); // Disputed
+      expect((await note(advanced, 0)).disputed).eq(true);
+      expect((await note(advanced, 1)).disputed).eq(true);
+    });
+
+    it("rejects a second dispute on the same order", async () => {
+      await expectError(openDispute(advanced, 2), credit, "DisputeAlreadyOpened");
+    });
+
+    it("keeps disputed notes out of the pool", async () => {
+      await expectError(advance(advanced, 1), pool, "NoteDisputed");
+    });
+
+    it("only lets the admin resolve before the deadline", async () => {
+      const beneficiaries = [poolVault, merchantAta];
+      await expectError(
+        resolveDispute(advanced, BUYER_WINS, beneficiaries, owner),
+        credit,
+        "Unauthorized"
+      );
+    });
+
+    it("cancels the notes and buys back the advanced one when the buyer wins", async () => {
+      const net = (await note(advanced, 0)).netAmount.toNumber();
+      const poolBefore = await balance(poolVault);
+      const vaultBefore = await balance(usdcVault);
+      // Sem assinatura do lojista: o cofre recompra e o lojista fica devendo
+      await resolveDispute(advanced, BUYER_WINS, [poolVault, merchantAta]);
+
+      expect(await balance(poolVault)).eq(poolBefore + net);
+      expect(await balance(usdcVault)).eq(vaultBefore - net);
+      const order = await orderRecord(advanced);
+      expect(order.status).eq(2); // Refunded
+      expect(order.merchantDebtAmount.toNumber()).eq(net);
+      for (const i of [0, 1]) {
+        const n = await note(advanced, i);
+        expect(n.status).eq(8);
+        expect(n.disputed).eq(false);
+      }
+      // Sobra só a compra não contestada
+      expect((await creditAccount(owner.publicKey)).usedAmount.toNumber()).eq(100 * USDC);
+    });
+
+    it("lets the merchant repay the buyback to the vault", async () => {
+      const debt = (await orderRecord(advanced)).merchantDebtAmount.toNumber();
+      await expectError(repayMerchantDebt(advanced, debt + 1), credit, "InvalidAmount");
+
+      const vaultBefore = await balance(usdcVault);
+      await repayMerchantDebt(advanced, debt);
+
+      expect(await balance(usdcVault)).eq(vaultBefore + debt);
+      expect((await orderRecord(advanced)).merchantDebtAmount.toNumber()).eq(0);
+    });
+
+    it("restores the installments when the merchant wins", async () => {
+      await openDispute(kept, 1);
+      await resolveDispute(kept, MERCHANT_WINS, [merchantAta]);
+
+      expect((await orderRecord(kept)).status).eq(0); // Open
+      const n = await note(kept, 0);
+      expect(n.disputed).eq(false);
+      expect(n.amount.toNumber()).eq(100 * USDC);
+      const acct = await creditAccount(owner.publicKey);
+      expect(acct.disputedAmount.toNumber()).eq(0);
+      expect(acct.usedAmount.toNumber()).eq(100 * USDC);
+    });
+
+    it("only buys back notes the pool actually advanced", async () => {
+      const assigned = orderId("dispute-003");
+      await charge(owner, assigned, 100, 1);
+      // Lojista cede a nota a outra carteira sem passar pelo pool
+      const wallet = await newUser();
+      const walletAta = await mintTokens(usdcMint, wallet.publicKey, 0);
+      await recv.methods
+        .assignBeneficiary(wallet.publicKey)
+        .accountsPartial({ beneficiary: merchant.publicKey, noteState: notePda(assigned, 0) })
+        .signers([merchant])
+        .preInstructions([nonce()])
+        .rpc();
+      await openDispute(assigned, 1);
+
+      const vaultBefore = await balance(usdcVault);
+      await resolveDispute(assigned, BUYER_WINS, [walletAta]);
+
+      expect(await balance(walletAta)).eq(0);
+      expect(await balance(usdcVault)).eq(vaultBefore);
+      expect((await orderRecord(assigned)).merchantDebtAmount.toNumber()).eq(0);
+      expect((await note(assigned, 0)).status).eq(8);
+    });
+  });
+
+  describe("autopay", () => {
+    let owner: Keypair;
+    let ownerAta: PublicKey;
+    const AUTOPAY_STATEMENT = 2;
+
+    function enableAutopay(mode: number) {
+      return credit.methods
+        .enableAutopay(mode, new BN(0), new BN(500 * USDC))
+        .accountsPartial({
+          owner: owner.publicKey,
+          creditAccount: creditPda(owner.publicKey),
+          config: configPda,
+          mint: usdcMint,
+          ownerAta,
+          vaultAuthority,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([owner])
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    // Crank sem assinatura do owner: quem paga a taxa é o admin
+    function runAutopay() {
+      return credit.methods
+        .runAutopay()
+        .accountsPartial({
+          creditAccount: creditPda(owner.publicKey),
+          owner: owner.publicKey,
+          config: configPda,
+          mint: usdcMint,
+          ownerAta,
+          vaultAuthority,
+          vault: usdcVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    before(async () => {
+      owner = await newUser();
+      await openAccount(owner);
+      await backWithCollateral(owner);
+      await charge(owner, orderId("autopay-001"), 100, 1);
+      ownerAta = await mintTokens(usdcMint, owner.publicKey, 200);
+    });
+
+    it("rejects an unknown autopay mode", async () => {
+      await expectError(enableAutopay(3), credit, "InvalidAutopayMode");
+    });
+
+    it("approves the vault authority as delegate of the owner ATA", async () => {
+      await enableAutopay(AUTOPAY_STATEMENT);
+
+      expect((await creditAccount(owner.publicKey)).autopayMode).eq(AUTOPAY_STATEMENT);
+      const info = await context.banksClient.getAccount(ownerAta);
+      const token = AccountLayout.decode(info!.data);
+      expect(new PublicKey(token.delegate).toBase58()).eq(vaultAuthority.toBase58());
+      expect(Number(token.delegatedAmount)).eq(500 * USDC);
+    });
+
+    it("does not run before the statement is due", async () => {
+      await expectError(runAutopay(), credit, "AutopayNotDue");
+    });
+
+    it("pulls the statement balance inside the due window", async () => {
+      await closeStatement(owner.publicKey);
+      const dueDate = (await creditAccount(owner.publicKey)).dueDateTs.toNumber();
+      await warpTo(dueDate - 2 * DAY);
+      const vaultBefore = await balance(usdcVault);
+
+      await runAutopay();
+
+      expect(await balance(ownerAta)).eq(100 * USDC);
+      expect(await balance(usdcVault)).eq(vaultBefore + 100 * USDC);
+      const acct = await creditAccount(owner.publicKey);
+      expect(acct.usedAmount.toNumber()).eq(0);
+      expect(acct.autopayLastCycle).eq(acct.cycleCount);
+    });
+
+    it("runs at most once per statement", async () => {
+      await expectError(runAutopay(), credit, "AutopayAlreadyRun");
+    });
+  });
+
+  describe("restructure", () => {
+    let owner: Keypair;
+    const overdue = orderId("restructure-old");
+    const renegotiated = orderId("restructure-new");
+    const offerPda = (o: PublicKey) => pda([seed("restructure"), o.toBuffer()], credit.programId);
+
+    function propose(expiresTs: number) {
+      return credit.methods
+        .proposeRestructure(300, 3, new BN(expiresTs))
+        .accountsPartial({
+          admin: admin.publicKey,
+          config: configPda,
+          creditAccount: creditPda(owner.publicKey),
+          owner: owner.publicKey,
+          offer: offerPda(owner.publicKey),
+          systemProgram: SystemProgram.programId,
+        })
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    /** Cancela as notas `oldIndices` do pedido vencido e emite as 3 parcelas novas. */
+    function accept(oldIndices: number[]) {
+      return credit.methods
+        .acceptRestructure(renegotiated, Buffer.from(oldIndices))
+        .accountsPartial({
+          owner: owner.publicKey,
+          creditAccount: creditPda(owner.publicKey),
+          config: configPda,
+          admin: admin.publicKey,
+          offer: offerPda(owner.publicKey),
+          orderRecord: orderPda(renegotiated),
+          vaultAuthority,
+          mint: usdcMint,
+          vault: usdcVault,
+          receivablesProgram: recv.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts([
+          ...oldIndices.flatMap((i) => [
+            readonly(orderPda(overdue)),
+            writable(notePda(overdue, i)),
+            writable(merchantAta),
+          ]),
+          ...[0, 1, 2].map((i) => writable(notePda(renegotiated, i))),
+        ])
+        .signers([owner])
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    before(async () => {
+      owner = await newUser();
+      await openAccount(owner);
+      await backWithCollateral(owner);
+      await charge(owner, overdue, 100, 1);
+      // Cofre do protocolo recompra do lojista a nota cancelada
+      await mintTokens(usdcMint, vaultAuthority, 100);
+    });
+
+    it("rejects an offer that is already expired", async () => {
+      await expectError(propose((await now()) - 1), credit, "RestructureExpired");
+    });
+
+    it("rejects accepting while nothing is overdue", async () => {
+      // Fechamento e vencimento podem cair até ~2 meses à frente
+      await propose((await now()) + 120 * DAY);
+      await expectError(accept([]), credit, "NothingToRestructure");
+    });
+
+    it("replaces the overdue note with a new installment plan", async () => {
+      await closeStatement(owner.publicKey);
+      await warpTo((await creditAccount(owner.publicKey)).dueDateTs.toNumber() + DAY);
+      const net = (await note(overdue, 0)).netAmount.toNumber();
+      const merchantBefore = await balance(merchantAta);
+      const vaultBefore = await balance(usdcVault);
+
+      await accept([0]);
+
+      // O lojista não perde o recebível: o cofre paga o líquido da nota cancelada
+      expect(await balance(merchantAta)).eq(merchantBefore + net);
+      expect(await balance(usdcVault)).eq(vaultBefore - net);
+      const old = await note(overdue, 0);
+      expect(old.amount.toNumber()).eq(0);
+      expect(old.status).eq(8); // cancelada
+      const order = await orderRecord(renegotiated);
+      expect(order.installments).eq(3);
+      expect(order.merchant.toBase58()).eq(vaultAuthority.toBase58());
+      for (const i of [0, 1, 2]) {
+        expect((await note(renegotiated, i)).buyer.toBase58()).eq(owner.publicKey.toBase58());
+      }
+      const acct = await creditAccount(owner.publicKey);
+      expect(acct.restructureCount).eq(1);
+      expect(acct.pastDueCyclesAmount.every((v: BN) => v.isZero())).eq(true);
+      expect(acct.statementBalanceAmount.toNumber()).eq(0);
+    });
+  });
+
+  describe("cash advance", () => {
+    let owner: Keypair;
+    let ownerAta: PublicKey;
+
+    const poolAccount = (): Promise<any> => pool.account.pool.fetch(poolPda);
+
+    function cashAdvance(amount: number, who = owner) {
+      return credit.methods
+        .cashAdvance(new BN(amount * USDC))
+        .accountsPartial({
+          owner: who.publicKey,
+          creditAccount: creditPda(who.publicKey),
+          config: configPda,
+          settlementPrice: null,
+          mint: usdcMint,
+          ownerAta: ata(usdcMint, who.publicKey),
+          vaultAuthority,
+          pool: poolPda,
+          poolVault,
+          advancePoolProgram: pool.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([who])
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    // Crank sem assinatura do owner
+    function returnCashToPool(who = owner) {
+      return credit.methods
+        .returnCashToPool()
+        .accountsPartial({
+          creditAccount: creditPda(who.publicKey),
+          owner: who.publicKey,
+          config: configPda,
+          mint: usdcMint,
+          vaultAuthority,
+          vault: usdcVault,
+          pool: poolPda,
+          poolVault,
+          advancePoolProgram: pool.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    // Conta com colateral de apoio (500 USDC ponderados) e sublimite de saque de 200
+    async function openCashAccount(): Promise<Keypair> {
+      const who = await newUser();
+      await openAccount(who);
+      await credit.methods
+        .setCashLimit(new BN(200 * USDC))
+        .accountsPartial({
+          admin: admin.publicKey,
+          config: configPda,
+          creditAccount: creditPda(who.publicKey),
+          owner: who.publicKey,
+        })
+        .rpc();
+      await backWithCollateral(who);
+      await mintTokens(usdcMint, who.publicKey, 0);
+      return who;
+    }
+
+    before(async () => {
+      owner = await openCashAccount();
+      await mintTokens(usdcMint, poolPda, 500);
+      ownerAta = ata(usdcMint, owner.publicKey);
+    });
+
+    it("draws cash from the pool against the line", async () => {
+      const disbursedBefore = (await poolAccount()).disbursedAmount.toNumber();
+      await cashAdvance(100);
+
+      expect(await balance(ownerAta)).eq(100 * USDC);
+      const acct = await creditAccount(owner.publicKey);
+      expect(acct.cashBalanceAmount.toNumber()).eq(100 * USDC);
+      expect(acct.usedAmount.toNumber()).eq(100 * USDC);
+      expect((await poolAccount()).disbursedAmount.toNumber()).eq(disbursedBefore + 100 * USDC);
+    });
+
+    it("rejects draws above the cash sublimit", async () => {
+      await expectError(cashAdvance(150), credit, "CashLimitExceeded");
+    });
+
+    it("has nothing to return before the owner repays", async () => {
+      await expectError(returnCashToPool(), credit, "NothingToReturn");
+    });
+
+    it("returns repaid cash principal to the pool", async () => {
+      await repay(owner, 50);
+      const repaid = (await creditAccount(owner.publicKey)).cashRepaidAmount.toNumber();
+      expect(repaid).gt(0);
+      const disbursedBefore = (await poolAccount()).disbursedAmount.toNumber();
+      const vaultBefore = await balance(poolVault);
+
+      await returnCashToPool();
+
+      expect(await balance(poolVault)).eq(vaultBefore + repaid);
+      expect((await poolAccount()).disbursedAmount.toNumber()).eq(disbursedBefore - repaid);
+      expect((await creditAccount(owner.publicKey)).cashRepaidAmount.toNumber()).eq(0);
+    });
+
+    it("projects the HF with the draw and its fee against the cash threshold", async () => {
+      // ~50 em aberto + 250 da compra: HF 500/300 passa para compras (1,2)...
+      await charge(owner, orderId("cash-001"), 250, 1);
+      // ...mas o saque levaria a dívida a ~360, HF < 1,5
+      await expectError(cashAdvance(60), credit, "HfTooLow");
+    });
+
+    it("refuses to close the account before repaid cash goes back to the pool", async () => {
+      const closer = await openCashAccount();
+      await cashAdvance(50, closer);
+      await mintTokens(usdcMint, closer.publicKey, 50);
+      await repay(closer, 50);
+      const closeAccount = () =>
+        credit.methods
+          .closeAccount()
+          .accountsPartial({
+            owner: closer.publicKey,
+            creditAccount: creditPda(closer.publicKey),
+            spendControls: null,
+            config: configPda,
+            mint: usdcMint,
+            ownerAta: ata(usdcMint, closer.publicKey),
+            vaultAuthority,
+            vault: usdcVault,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .remainingAccounts([
+            readonly(positionIndexPda(closer.publicKey)),
+            readonly(positionPda(closer.publicKey, backing)),
+          ])
+          .signers([closer])
+          .preInstructions([nonce()])
+          .rpc();
+      await expectError(closeAccount(), credit, "CashNotReturned");
+
+      // Devolvido o saque, o que ainda impede o encerramento é o colateral
+      await returnCashToPool(closer);
+      await expectError(closeAccount(), credit, "CollateralLocked");
+    });
+
+    it("rejects a cash HF threshold not above the one for charges", async () => {
+      for (const minHfBpsForCash of [11_000, 12_000]) {
+        await expectError(
+          credit.methods
+            .proposeConfigUpdate({ ...configParams(), minHfBpsForCash })
+            .accountsPartial({ admin: admin.publicKey, config: configPda })
+            .preInstructions([nonce()])
+            .rpc(),
+          credit,
+          "InvalidConfig"
+        );
+      }
+    });
+
+    it("requires a fresh health factor", async () => {
+      await warpTo((await now()) + 2 * 3_600);
+      await expectError(cashAdvance(10), credit, "StaleHealthFactor");
+    });
+  });
+
+  describe("settlement mints", () => {
+    let owner: Keypair;
+    let usdt: PublicKey;
+    const id = orderId("usdt-001");
+
+    function setSettlementMint(mint: PublicKey, signer: Keypair = admin) {
+      return credit.methods
+        .setSettlementMint(true)
+        .accountsPartial({
+          admin: signer.publicKey,
+          config: configPda,
+          mint,
+          vaultAuthority,
+          vault: ata(mint, vaultAuthority),
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([signer])
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    before(async () => {
+      owner = await newUser();
+      usdt = await createMint();
+    });
+
+    it("rejects accounts in a mint that is not listed", async () => {
+      await expectError(openAccount(owner, 1_000, usdt), credit, "MintNotAllowed");
+    });
+
+    it("only lets the admin list a stablecoin", async () => {
+      await expectError(setSettlementMint(usdt, owner), credit, "ConstraintHasOne");
+    });
+
+    it("lists a stablecoin and opens an account in it", async () => {
+      await setSettlementMint(usdt);
+
+      const cfg: any = await credit.account.config.fetch(configPda);
+      expect(cfg.settlementMintCount).eq(2);
+      expect(cfg.settlementMints[1].mint.toBase58()).eq(usdt.toBase58());
+      expect(cfg.settlementMints[1].enabled).eq(true);
+      // Cofre do protocolo na nova moeda
+      expect(await context.banksClient.getAccount(ata(usdt, vaultAuthority))).to.not.equal(null);
+
+      await openAccount(owner, 1_000, usdt);
+      expect((await creditAccount(owner.publicKey)).mint.toBase58()).eq(usdt.toBase58());
+    });
+
+    it("values the account only with the stablecoin price from the collateral vault", async () => {
+      await depositCollateral(owner, backing, 1_000, 5_000);
+      await setPrice(backing, 1);
+      const refresh = credit.methods
+        .refreshHealth()
+        .accountsPartial({
+          creditAccount: creditPda(owner.publicKey),
+          owner: owner.publicKey,
+          config: configPda,
+          settlementPrice: null,
+        })
+        .remainingAccounts(collateralAccounts(owner.publicKey, [backing]))
+        .preInstructions([nonce()])
+        .rpc();
+      await expectError(refresh, credit, "SettlementPriceMissing");
+
+      await setPrice(usdt, 0.999);
+      await refreshHealth(owner.publicKey, [backing]);
+      expect((await creditAccount(owner.publicKey)).lastHealthTs.toNumber()).gt(0);
+    });
+
+    it("carries the mint on orders and notes and keeps them out of the USDC pool", async () => {
+      await charge(owner, id, 100, 1);
+
+      expect((await orderRecord(id)).mint.toBase58()).eq(usdt.toBase58());
+      expect((await note(id, 0)).mint.toBase58()).eq(usdt.toBase58());
+      await expectError(advance(id, 0), pool, "MintMismatch");
+    });
+
+    it("requires a fresh settlement price to value the account", async () => {
+      await warpTo((await now()) + 2 * 3_600);
+      await expectError(refreshHealth(owner.publicKey, [backing]), credit, "StaleSettlementRate");
+    });
+  });
+
+  describe("score", () => {
+    let owner: Keypair;
+
+    before(async () => {
+      owner = await newUser();
+      await openAccount(owner);
+      await backWithCollateral(owner);
+      await charge(owner, orderId("score-001"), 300, 3);
+      await mintTokens(usdcMint, owner.publicKey, 100);
+      await closeStatement(owner.publicKey);
+    });
+
+    it("counts a minimum paid by the due date as on time and raises the score", async () => {
+      const before = await creditAccount(owner.publicKey);
+      await repay(owner, before.minPaymentDueAmount.toNumber() / USDC);
+
+      const acct = await creditAccount(owner.publicKey);
+      expect(acct.onTimePayments).eq(1);
+      expect(acct.latePayments).eq(0);
+      expect(acct.score).gt(before.score);
+    });
+
+    it("counts a minimum paid after the due date as late and lowers the score", async () => {
+      await closeStatement(owner.publicKey);
+      const billed = await creditAccount(owner.publicKey);
+      await warpTo(billed.dueDateTs.toNumber() + 3 * DAY);
+      await repay(owner, 40);
+
+      const acct = await creditAccount(owner.publicKey);
+      expect(acct.minPaymentDueAmount.toNumber()).eq(0);
+      expect(acct.latePayments).eq(1);
+      expect(acct.score).lt(billed.score);
+    });
+  });
+
+  describe("freeze", () => {
+    let owner: Keypair;
+    const FRAUD = 2;
+
+    function freeze(kind: "softFreeze" | "hardFreeze", reason: number, signer: Keypair = admin) {
+      return credit.methods[kind](reason)
+        .accountsPartial({
+          authority: signer.publicKey,
+          creditAccount: creditPda(owner.publicKey),
+          owner: owner.publicKey,
+          config: configPda,
+        })
+        .signers([signer])
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    function unfreeze() {
+      return credit.methods
+        .unfreeze()
+        .accountsPartial({
+          authority: admin.publicKey,
+          creditAccount: creditPda(owner.publicKey),
+          owner: owner.publicKey,
+          config: configPda,
+        })
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    before(async () => {
+      owner = await newUser();
+      await openAccount(owner);
+    });
+
+    it("only lets the risk authority freeze", async () => {
+      const stranger = await newUser();
+      await expectError(freeze("softFreeze", FRAUD, stranger), credit, "Unauthorized");
+    });
+
+    it("requires a freeze reason", async () => {
+      await expectError(freeze("softFreeze", 0), credit, "InvalidFreezeReason");
+    });
+
+    it("soft-freezes with a reason and blocks charges", async () => {
+      await freeze("softFreeze", FRAUD);
+      const acct = await creditAccount(owner.publicKey);
+      expect(acct.status).eq(1);
+      expect(acct.freezeReason).eq(FRAUD);
+      expect(acct.preFreezeStatus).eq(0);
+      await expectError(charge(owner, orderId("frozen-001"), 10, 1), credit, "AccountFrozen");
+    });
+
+    it("escalates to a hard freeze keeping the status to return to", async () => {
+      await freeze("hardFreeze", FRAUD);
+      const acct = await creditAccount(owner.publicKey);
+      expect(acct.status).eq(2);
+      expect(acct.preFreezeStatus).eq(0);
+    });
+
+    it("unfreezes back to the previous status", async () => {
+      await unfreeze();
+      const acct = await creditAccount(owner.publicKey);
+      expect(acct.status).eq(0);
+      expect(acct.freezeReason).eq(0);
+      await expectError(unfreeze(), credit, "AccountNotFrozen");
+    });
+  });
+
+  describe("spend controls", () => {
+    let owner: Keypair;
+    const GAMBLING = 3;
+    const controlsPda = () => pda([seed("controls"), owner.publicKey.toBuffer()], credit.programId);
+    const merchantProfilePda = () =>
+      pda([seed("merchant"), merchant.publicKey.toBuffer()], credit.programId);
+
+    function setSpendControls(blockedCategories: number) {
+      return credit.methods
+        .setSpendControls({
+          dailyLimitAmount: new BN(0),
+          weeklyLimitAmount: new BN(0),
+          maxTxPerDay: 0,
+          perTxMaxAmount: new BN(0),
+          blockedCategories,
+        })
+        .accountsPartial({
+          owner: owner.publicKey,
+          creditAccount: creditPda(owner.publicKey),
+          spendControls: controlsPda(),
+        })
+        .signers([owner])
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    function setMerchantCategory(category: number) {
+      return credit.methods
+        .setMerchantCategory(category)
+        .accountsPartial({
+          admin: admin.publicKey,
+          config: configPda,
+          merchant: merchant.publicKey,
+          merchantProfile: merchantProfilePda(),
+        })
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    function chargeWithControls(id: number[], withProfile: boolean) {
+      return credit.methods
+        .charge(new BN(10 * USDC), 1, id, PLAN_MERCHANT_ABSORBED)
+        .accountsPartial({
+          authority: owner.publicKey,
+          merchant: merchant.publicKey,
+          creditAccount: creditPda(owner.publicKey),
+          owner: owner.publicKey,
+          delegate: null,
+          spendControls: controlsPda(),
+          merchantProfile: withProfile ? merchantProfilePda() : null,
+          config: configPda,
+          settlementPrice: null,
+          orderRecord: orderPda(id),
+          vaultAuthority,
+          receivablesProgram: recv.programId,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts([writable(notePda(id, 0))])
+        .signers([owner, merchant])
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    before(async () => {
+      owner = await newUser();
+      await openAccount(owner);
+      await backWithCollateral(owner);
+    });
+
+    it("does not need the merchant profile when no category is blocked", async () => {
+      await setSpendControls(0);
+      await chargeWithControls(orderId("controls-001"), false);
+      expect((await creditAccount(owner.publicKey)).usedAmount.toNumber()).eq(10 * USDC);
+    });
+
+    it("requires a registered merchant once a category is blocked", async () => {
+      await setSpendControls(1 << GAMBLING);
+      await expectError(
+        chargeWithControls(orderId("controls-002"), false),
+        credit,
+        "MerchantNotRegistered"
+      );
+    });
+
+    it("checks the registered category against the blocked ones", async () => {
+      await setMerchantCategory(GAMBLING);
+      await expectError(
+        chargeWithControls(orderId("controls-002"), true),
+        credit,
+        "CategoryBlocked"
+      );
+      await setMerchantCategory(GAMBLING + 1);
+      await chargeWithControls(orderId("controls-002"), true);
+      expect((await creditAccount(owner.publicKey)).usedAmount.toNumber()).eq(20 * USDC);
+    });
+  });
+
+  describe("close account", () => {
+    let owner: Keypair;
+    const controlsPda = () => pda([seed("controls"), owner.publicKey.toBuffer()], credit.programId);
+
+    function closeAccount(withControls: boolean) {
+      return credit.methods
+        .closeAccount()
+        .accountsPartial({
+          owner: owner.publicKey,
+          creditAccount: creditPda(owner.publicKey),
+          spendControls: withControls ? controlsPda() : null,
+          config: configPda,
+          mint: usdcMint,
+          ownerAta: ata(usdcMint, owner.publicKey),
+          vaultAuthority,
+          vault: usdcVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(collateralAccounts(owner.publicKey))
+        .signers([owner])
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    before(async () => {
+      owner = await newUser();
+      await openAccount(owner);
+      await mintTokens(usdcMint, owner.publicKey, 0);
+      await credit.methods
+        .setSpendControls({
+          dailyLimitAmount: new BN(50 * USDC),
+          weeklyLimitAmount: new BN(0),
+          maxTxPerDay: 0,
+          perTxMaxAmount: new BN(0),
+          blockedCategories: 0,
+        })
+        .accountsPartial({
+          owner: owner.publicKey,
+          creditAccount: creditPda(owner.publicKey),
+          spendControls: controlsPda(),
+        })
+        .signers([owner])
+        .rpc();
+    });
+
+    it("requires the spend controls when the account has them", async () => {
+      await expectError(closeAccount(false), credit, "SpendControlsMissing");
+    });
+
+    it("closes the spend controls together with the account", async () => {
+      await closeAccount(true);
+      expect(await context.banksClient.getAccount(creditPda(owner.publicKey))).eq(null);
+      expect(await context.banksClient.getAccount(controlsPda())).eq(null);
+    });
+
+    it("starts a new statement series when the account is reopened", async () => {
+      await openAccount(owner);
+      await closeStatement(owner.publicKey);
+      const first = await creditAccount(owner.publicKey);
+      await closeAccount(false);
+
+      // A fatura 1 da abertura anterior continua lá; a reaberta fecha a sua própria
+      await openAccount(owner);
+      const reopened = await creditAccount(owner.publicKey);
+      expect(reopened.cycleCount).eq(0);
+      await closeStatement(owner.publicKey);
+
+      expect((await credit.account.statement.fetch(statementPda(first, 1))).cycleIndex).eq(1);
+      const st = await credit.account.statement.fetch(statementPda(reopened, 1));
+      expect(st.cycleIndex).eq(1);
+      expect(st.closedTs.toNumber()).gt(first.openedTs.toNumber());
+    });
+  });
+
+  // Avança o relógio ~3 meses: perto do fim para não envelhecer preços dos outros blocos
+  describe("account status", () => {
+    let late: Keypair; // fica sem pagar até o default
+    let cured: Keypair; // paga o mínimo depois da carência
+    let dueDate: number;
+    const ACTIVE = 0;
+    const DELINQUENT = 3;
+    const DEFAULTED = 4;
+    const pumpTokenPda = (mint: PublicKey) =>
+      pda([seed("pump_token"), mint.toBuffer()], vault.programId);
+
+    // `remaining_accounts`: PositionIndex e, por posição, [Position, PumpToken]
+    function updateStatus(owner: PublicKey) {
+      return credit.methods
+        .updateStatus()
+        .accountsPartial({
+          creditAccount: creditPda(owner),
+          owner,
+          config: configPda,
+          settlementPrice: null,
+        })
+        .remainingAccounts([
+          readonly(positionIndexPda(owner)),
+          readonly(positionPda(owner, backing)),
+          readonly(pumpTokenPda(backing)),
+        ])
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    before(async () => {
+      late = await newUser();
+      cured = await newUser();
+      for (const [owner, id] of [
+        [late, "status-001"],
+        [cured, "status-002"],
+      ] as const) {
+        await openAccount(owner);
+        await backWithCollateral(owner);
+        await charge(owner, orderId(id), 100, 1);
+        await closeStatement(owner.publicKey);
+      }
+      dueDate = (await creditAccount(late.publicKey)).dueDateTs.toNumber();
+      await mintTokens(usdcMint, cured.publicKey, 100);
+    });
+
+    it("stays active inside the grace period", async () => {
+      await warpTo(dueDate + 10 * DAY);
+      await updateStatus(late.publicKey);
+      expect((await creditAccount(late.publicKey)).status).eq(ACTIVE);
+    });
+
+    it("turns delinquent after the grace period", async () => {
+      // Colateral não volátil: carência de `graceAnyDays` (30)
+      await warpTo(dueDate + 31 * DAY);
+      for (const owner of [late, cured]) {
+        await updateStatus(owner.publicKey);
+        const acct = await creditAccount(owner.publicKey);
+        expect(acct.status).eq(DELINQUENT);
+        expect(acct.freezeReason).eq(1); // PastDue
+      }
+    });
+
+    it("cures back to active once the minimum is paid", async () => {
+      await repay(cured, 50);
+      await updateStatus(cured.publicKey);
+      const acct = await creditAccount(cured.publicKey);
+      expect(acct.status).eq(ACTIVE);
+      expect(acct.freezeReason).eq(0);
+    });
+
+    it("defaults after the default period and does not cure", async () => {
+      // Carência (30) + `defaultAfterDays` (60)
+      await warpTo(dueDate + 91 * DAY);
+      await updateStatus(late.publicKey);
+      expect((await creditAccount(late.publicKey)).status).eq(DEFAULTED);
+
+      await mintTokens(usdcMint, late.publicKey, 50);
+      await repay(late, 50);
+      await updateStatus(late.publicKey);
+      expect((await creditAccount(late.publicKey)).status).eq(DEFAULTED);
+    });
+
+    it("charges off the account and defaults its unpaid notes", async () => {
+      const forceClose = (owner: PublicKey, id: number[]) =>
+        credit.methods
+          .forceCloseAccount(Buffer.from([0]))
+          .accountsPartial({
+            admin: admin.publicKey,
+            config: configPda,
+            creditAccount: creditPda(owner),
+            owner,
+            vaultAuthority,
+            pool: poolPda,
+            receivablesProgram: recv.programId,
+            advancePoolProgram: pool.programId,
+          })
+          .remainingAccounts([readonly(orderPda(id)), writable(notePda(id, 0))])
+          .preInstructions([nonce()])
+          .rpc();
+      await expectError(
+        forceClose(cured.publicKey, orderId("status-002")),
+        credit,
+        "AccountNotDefaulted"
+      );
+
+      await forceClose(late.publicKey, orderId("status-001"));
+      const acct = await creditAccount(late.publicKey);
+      expect(acct.status).eq(5); // Closed
+      expect(acct.usedAmount.toNumber()).eq(0);
+      expect((await note(orderId("status-001"), 0)).status).eq(6); // defaulted
+    });
+
+    it("keeps the collateral of a charged-off account locked", async () => {
+      await expectError(
+        credit.methods
+          .withdrawCollateral(new BN(1 * USDC))
+          .accountsPartial({
+            owner: late.publicKey,
+            creditAccount: creditPda(late.publicKey),
+            config: configPda,
+            settlementPrice: null,
+            vaultAuthority,
+            vaultConfig: vaultConfigPda,
+            collateralVault: collateralVaultPda(backing),
+            mint: backing,
+            position: positionPda(late.publicKey, backing),
+            priceAccount: pricePda(backing),
+            vaultAta: ata(backing, collateralVaultPda(backing)),
+            ownerAta: ata(backing, late.publicKey),
+            collateralVaultProgram: vault.programId,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .remainingAccounts(collateralAccounts(late.publicKey, [backing]))
+          .signers([late])
+          .preInstructions([nonce()])
+          .rpc(),
+        credit,
+        "ChargedOff"
+      );
+    });
+  });
+
+  // Avança o relógio um dia: fica por último para não envelhecer preços dos outros blocos
+  describe("config timelock", () => {
+    const fetchConfig = (): Promise<any> => credit.account.config.fetch(configPda);
+
+    function propose(params: any, signer: Keypair = admin) {
+      return credit.methods
+        .proposeConfigUpdate(params)
+        .accountsPartial({ admin: signer.publicKey, config: configPda })
+        .signers([signer])
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    function apply() {
+      return credit.methods
+        .applyConfigUpdate()
+        .accountsPartial({ config: configPda })
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    function proposeAdmin(newAdmin: PublicKey, signer: Keypair = admin) {
+      return credit.methods
+        .proposeAdmin(newAdmin)
+        .accountsPartial({ admin: signer.publicKey, config: configPda })
+        .signers([signer])
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    function acceptAdmin(signer: Keypair) {
+      return credit.methods
+        .acceptAdmin()
+        .accountsPartial({ newAdmin: signer.publicKey, config: configPda })
+        .signers([signer])
+        .preInstructions([nonce()])
+        .rpc();
+    }
+
+    it("only lets the admin propose", async () => {
+      const stranger = await newUser();
+      await expectError(propose(configParams(), stranger), credit, "ConstraintHasOne");
+    });
+
+    it("applies a proposal only after the timelock", async () => {
+      await propose({ ...configParams(), lateFeeBps: 250 });
+      await expectError(apply(), credit, "TimelockNotElapsed");
+
+      const cfg = await fetchConfig();
+      expect(cfg.lateFeeBps).eq(200);
+      await warpTo(cfg.pendingEta.toNumber());
+      await apply();
+
+      const applied = await fetchConfig();
+      expect(applied.lateFeeBps).eq(250);
+      expect(applied.pendingEta.toNumber()).eq(0);
+    });
+
+    it("cancels a pending proposal", async () => {
+      await propose({ ...configParams(), lateFeeBps: 300 });
+      await credit.methods
+        .cancelConfigUpdate()
+        .accountsPartial({ admin: admin.publicKey, config: configPda })
+        .rpc();
+
+      await warpTo((await now()) + DAY);
+      await expectError(apply(), credit, "NoPendingUpdate");
+      expect((await fetchConfig()).lateFeeBps).eq(250);
+    });
+
+    it("hands the admin role over in two steps", async () => {
+      const next = await newUser();
+      const stranger = await newUser();
+      await proposeAdmin(next.publicKey);
+      await expectError(acceptAdmin(stranger), credit, "Unauthorized");
+
+      await acceptAdmin(next);
+      expect((await fetchConfig()).admin.toBase58()).eq(next.publicKey.toBase58());
+      await expectError(proposeAdmin(stranger.publicKey), credit, "ConstraintHasOne");
+
+      // Devolve o cargo para os blocos seguintes
+      await proposeAdmin(admin.publicKey, next);
+      await acceptAdmin(admin);
+      expect((await fetchConfig()).admin.toBase58()).eq(admin.publicKey.toBase58());
+    });
   });
 });